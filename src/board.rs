@@ -0,0 +1,330 @@
+use std::ops::{Index, IndexMut};
+
+use crate::matches::{group_runs, MatchGroup, Run};
+use crate::tile::{Special, Tile};
+
+/// 一次下落：某列中的方块从 `from_row` 落到 `to_row`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fall {
+    pub col: usize,      // 所在列
+    pub from_row: usize, // 下落前的行
+    pub to_row: usize,   // 下落后的行
+    pub tile: Tile,      // 下落的方块
+}
+
+/// 游戏棋盘，按行存储每个格子的宝石
+///
+/// 通过 `board[(行, 列)]` 读写单个格子。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    width: usize,    // 列数
+    height: usize,   // 行数
+    cells: Vec<Tile>, // 按行展开的格子，长度为 width * height
+}
+
+impl Board {
+    /// 创建全部为空的棋盘
+    pub fn new(width: usize, height: usize) -> Self {
+        Board {
+            width,
+            height,
+            cells: vec![Tile::EMPTY; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// 按行优先顺序遍历所有位置
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |i| (0..width).map(move |j| (i, j)))
+    }
+
+    /// 交换两个位置的方块（不检查是否相邻）
+    pub fn swap(&mut self, a: (usize, usize), b: (usize, usize)) {
+        let (ia, ib) = (self.offset(a), self.offset(b));
+        self.cells.swap(ia, ib);
+    }
+
+    /// 棋盘上是否还有空位
+    pub fn has_empty(&self) -> bool {
+        self.cells.iter().any(Tile::is_empty)
+    }
+
+    fn offset(&self, (row, col): (usize, usize)) -> usize {
+        assert!(
+            row < self.height && col < self.width,
+            "位置 ({row}, {col}) 超出 {}x{} 棋盘",
+            self.width,
+            self.height
+        );
+        row * self.width + col
+    }
+
+    /// 查找所有可以消除的匹配（三个或更多连续相同），共用格子的横竖直线合并为 L/T 形匹配组
+    pub fn find_matches(&self) -> Vec<MatchGroup> {
+        group_runs(&self.find_runs())
+    }
+
+    /// 查找所有横向和纵向的连续同色宝石
+    pub(crate) fn find_runs(&self) -> Vec<Run> {
+        let mut runs = Vec::new();
+        // 检查水平匹配
+        for i in 0..self.height {
+            self.scan_line((0..self.width).map(|j| (i, j)), true, &mut runs);
+        }
+        // 检查垂直匹配
+        for j in 0..self.width {
+            self.scan_line((0..self.height).map(|i| (i, j)), false, &mut runs);
+        }
+        runs
+    }
+
+    // 沿一行或一列扫描，把长度至少为 3 的同色段加入 runs
+    fn scan_line(&self, line: impl Iterator<Item = (usize, usize)>, horizontal: bool, runs: &mut Vec<Run>) {
+        let mut current: Vec<(usize, usize)> = Vec::new();
+        for pos in line {
+            let color = self[pos].color;
+            if color != 0 && current.last().is_some_and(|&last| self[last].color == color) {
+                current.push(pos);
+                continue;
+            }
+            self.push_run(&mut current, horizontal, runs);
+            current.push(pos);
+        }
+        self.push_run(&mut current, horizontal, runs);
+    }
+
+    fn push_run(&self, current: &mut Vec<(usize, usize)>, horizontal: bool, runs: &mut Vec<Run>) {
+        let cells = std::mem::take(current);
+        if cells.len() >= 3 && self[cells[0]].color != 0 {
+            runs.push(Run {
+                color: self[cells[0]].color,
+                cells,
+                horizontal,
+            });
+        }
+    }
+
+    /// 在某个位置放上指定颜色的宝石后，是否会与横向或纵向相邻的同色宝石连成三个或更多
+    pub fn would_match(&self, (row, col): (usize, usize), color: u8) -> bool {
+        if color == 0 {
+            return false;
+        }
+        let same = |pos: (usize, usize)| self[pos].color == color;
+        let left = (0..col).rev().take_while(|&j| same((row, j))).count();
+        let right = (col + 1..self.width).take_while(|&j| same((row, j))).count();
+        let up = (0..row).rev().take_while(|&i| same((i, col))).count();
+        let down = (row + 1..self.height).take_while(|&i| same((i, col))).count();
+        left + right >= 2 || up + down >= 2
+    }
+
+    /// 某个位置上的特殊宝石被消除时波及的位置（不含自身；普通宝石返回空列表）
+    pub fn blast_area(&self, (row, col): (usize, usize)) -> Vec<(usize, usize)> {
+        match self[(row, col)].special {
+            Special::None | Special::Blocker => Vec::new(),
+            Special::StripedH => (0..self.width).filter(|&j| j != col).map(|j| (row, j)).collect(),
+            Special::StripedV => (0..self.height).filter(|&i| i != row).map(|i| (i, col)).collect(),
+            Special::Wrapped | Special::WrappedPrimed => self.area((row, col), 1),
+            // 被其他特殊宝石波及的彩色炸弹清除棋盘上最多的那种颜色
+            Special::ColorBomb => match self.most_common_color() {
+                Some(color) => self.positions_of_color(color),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    /// 上下左右相邻的位置（超出棋盘的部分忽略）
+    pub fn neighbors(&self, (row, col): (usize, usize)) -> Vec<(usize, usize)> {
+        let mut neighbors = Vec::with_capacity(4);
+        if row > 0 {
+            neighbors.push((row - 1, col));
+        }
+        if row + 1 < self.height {
+            neighbors.push((row + 1, col));
+        }
+        if col > 0 {
+            neighbors.push((row, col - 1));
+        }
+        if col + 1 < self.width {
+            neighbors.push((row, col + 1));
+        }
+        neighbors
+    }
+
+    /// 以某个位置为中心的十字区域：与中心行距不超过 `radius` 的整行，加上列距不超过 `radius` 的整列
+    pub fn cross(&self, (row, col): (usize, usize), radius: usize) -> Vec<(usize, usize)> {
+        self.positions()
+            .filter(|&(i, j)| i.abs_diff(row) <= radius || j.abs_diff(col) <= radius)
+            .collect()
+    }
+
+    /// 棋盘上所有指定颜色的宝石位置
+    pub fn positions_of_color(&self, color: u8) -> Vec<(usize, usize)> {
+        self.positions().filter(|&pos| self[pos].color == color).collect()
+    }
+
+    /// 棋盘上数量最多的颜色（数量相同时取颜色值较小的），棋盘上没有宝石时返回 None
+    pub fn most_common_color(&self) -> Option<u8> {
+        let mut counts = [0usize; u8::MAX as usize + 1];
+        for tile in &self.cells {
+            if tile.color != 0 {
+                counts[tile.color as usize] += 1;
+            }
+        }
+        (1..=u8::MAX)
+            .filter(|&color| counts[color as usize] > 0)
+            .max_by_key(|&color| (counts[color as usize], std::cmp::Reverse(color)))
+    }
+
+    /// 以某个位置为中心、半径为 `radius` 的方形区域内的其他位置（超出棋盘的部分忽略）
+    pub fn area(&self, (row, col): (usize, usize), radius: usize) -> Vec<(usize, usize)> {
+        let rows = row.saturating_sub(radius)..(row + radius + 1).min(self.height);
+        let cols = col.saturating_sub(radius)..(col + radius + 1).min(self.width);
+        rows.flat_map(|i| cols.clone().map(move |j| (i, j)))
+            .filter(|&pos| pos != (row, col))
+            .collect()
+    }
+
+    /// 计算每个方块需要下落的距离（不修改棋盘）
+    ///
+    /// 障碍不会下落，上方的方块落到障碍上为止。
+    pub fn falls(&self) -> Vec<Fall> {
+        let mut falls = Vec::new();
+        for j in 0..self.width {
+            let mut write_pos = self.height;
+            for read_pos in (0..self.height).rev() {
+                let tile = self[(read_pos, j)];
+                if tile.is_blocker() {
+                    write_pos = read_pos;
+                } else if !tile.is_empty() {
+                    write_pos -= 1;
+                    if write_pos != read_pos {
+                        falls.push(Fall {
+                            col: j,
+                            from_row: read_pos,
+                            to_row: write_pos,
+                            tile,
+                        });
+                    }
+                }
+            }
+        }
+        falls
+    }
+
+    /// 让所有方块落到最下方的空位
+    pub fn apply_gravity(&mut self) {
+        for fall in self.falls() {
+            self[(fall.to_row, fall.col)] = fall.tile;
+            self[(fall.from_row, fall.col)] = Tile::EMPTY;
+        }
+    }
+}
+
+impl Index<(usize, usize)> for Board {
+    type Output = Tile;
+
+    fn index(&self, pos: (usize, usize)) -> &Tile {
+        &self.cells[self.offset(pos)]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Board {
+    fn index_mut(&mut self, pos: (usize, usize)) -> &mut Tile {
+        let offset = self.offset(pos);
+        &mut self.cells[offset]
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::matches::{MatchShape, Orientation};
+
+    // 用字符串画出棋盘：`.` 空格，`#` 障碍，`*` 彩色炸弹，数字为宝石颜色
+    pub(crate) fn board(rows: &[&str]) -> Board {
+        let mut board = Board::new(rows[0].len(), rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            for (j, code) in row.chars().enumerate() {
+                board[(i, j)] = match code {
+                    '.' => Tile::EMPTY,
+                    '#' => Tile::BLOCKER,
+                    '*' => Tile::COLOR_BOMB,
+                    _ => Tile::gem(code.to_digit(10).expect("无法识别的格子") as u8),
+                };
+            }
+        }
+        board
+    }
+
+    // 棋盘上唯一的匹配组
+    fn single_group(rows: &[&str]) -> MatchGroup {
+        let mut groups = board(rows).find_matches();
+        assert_eq!(groups.len(), 1, "{groups:?}");
+        groups.remove(0)
+    }
+
+    #[test]
+    fn straight_lines() {
+        let three = single_group(&["111..", ".....", "....."]);
+        assert_eq!(three.shape, MatchShape::Three);
+        assert_eq!(three.orientation, Orientation::Horizontal);
+        assert_eq!(three.cells, vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(three.pivot, None);
+
+        let four = single_group(&["..2..", "..2..", "..2..", "..2..", "....."]);
+        assert_eq!(four.shape, MatchShape::Four);
+        assert_eq!(four.orientation, Orientation::Vertical);
+        assert_eq!(four.color, 2);
+
+        let five = single_group(&["33333", ".....", "....."]);
+        assert_eq!(five.shape, MatchShape::Five);
+        assert_eq!(five.cells.len(), 5);
+    }
+
+    #[test]
+    fn corner_is_l_shape() {
+        let group = single_group(&["1....", "1....", "111..", "....."]);
+        assert_eq!(group.shape, MatchShape::L);
+        assert_eq!(group.pivot, Some((2, 0)));
+        assert_eq!(group.cells.len(), 5);
+    }
+
+    #[test]
+    fn crossing_in_the_middle_is_t_shape() {
+        let t = single_group(&["111", ".1.", ".1."]);
+        assert_eq!(t.shape, MatchShape::T);
+        assert_eq!(t.pivot, Some((0, 1)));
+
+        let plus = single_group(&[".4.", "444", ".4."]);
+        assert_eq!(plus.shape, MatchShape::T);
+        assert_eq!(plus.pivot, Some((1, 1)));
+        assert_eq!(plus.cells.len(), 5);
+    }
+
+    #[test]
+    fn five_in_a_line_wins_over_crossing() {
+        let group = single_group(&["..1..", "..1..", "11111"]);
+        assert_eq!(group.shape, MatchShape::Five);
+        assert_eq!(group.orientation, Orientation::Horizontal);
+        assert_eq!(group.pivot, Some((2, 2)));
+    }
+
+    #[test]
+    fn separate_lines_are_separate_groups() {
+        let groups = board(&["111..", ".....", "..222"]).find_matches();
+        assert_eq!(groups.len(), 2);
+        assert!(groups.iter().all(|group| group.shape == MatchShape::Three));
+
+        // 空格、障碍和彩色炸弹不会连成匹配
+        assert!(board(&["...", "###", "***"]).find_matches().is_empty());
+        assert!(board(&["11211", "22122"]).find_matches().is_empty());
+    }
+}
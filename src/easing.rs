@@ -0,0 +1,56 @@
+// 动画缓动曲线：把动画进度（0 到 1）映射为位置或大小的变化比例
+use serde::{Deserialize, Serialize};
+
+const BOUNCE_START: f32 = 0.8;   // 弹跳曲线中落地的时刻（之前加速下落，之后弹起再落回）
+const BOUNCE_HEIGHT: f32 = 0.06; // 弹起的高度占整段距离的比例
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,  // 匀速
+    #[default]
+    EaseOut, // 先快后慢
+    Bounce,  // 加速下落，落地后轻轻弹起一次
+}
+
+impl Easing {
+    pub const ALL: [Easing; 3] = [Easing::Linear, Easing::EaseOut, Easing::Bounce];
+
+    // 在设置栏中显示的名字
+    pub fn name(&self) -> &'static str {
+        match self {
+            Easing::Linear => "线性",
+            Easing::EaseOut => "缓出",
+            Easing::Bounce => "弹跳",
+        }
+    }
+
+    // 计算进度 t 对应的变化比例（t 超出 0 到 1 时按端点处理）
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::Bounce if t < BOUNCE_START => (t / BOUNCE_START).powi(2),
+            Easing::Bounce => {
+                let u = (t - BOUNCE_START) / (1.0 - BOUNCE_START);
+                1.0 - BOUNCE_HEIGHT * (u * std::f32::consts::PI).sin()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_start_at_zero_and_end_at_one() {
+        for easing in Easing::ALL {
+            assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{easing:?}");
+            // 超出范围的进度按端点处理
+            assert_eq!(easing.apply(-1.0), easing.apply(0.0));
+            assert_eq!(easing.apply(2.0), easing.apply(1.0));
+        }
+    }
+}
@@ -0,0 +1,1320 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Fall};
+use crate::matches::{MatchGroup, MatchShape, Orientation};
+use crate::spawn::{SpawnPolicy, SpawnRule};
+use crate::tile::{Special, Tile};
+
+/// 支持的最多颜色数
+pub const MAX_COLORS: u8 = 8;
+
+/// 连锁消除达到此轮数后，每多一轮奖励限时模式的时间
+pub const CASCADE_BONUS_DEPTH: u32 = 3;
+/// 每轮大连锁奖励的秒数
+pub const CASCADE_BONUS_SECONDS: f32 = 2.0;
+/// 被特殊宝石波及而消除的每个方块的得分
+pub const BLAST_POINTS_PER_TILE: u32 = 20;
+/// 没有指定过关目标时的目标分数
+pub const DEFAULT_TARGET_SCORE: u32 = 2000;
+/// 过关时每剩余一步奖励的分数
+pub const BONUS_POINTS_PER_MOVE: u32 = 100;
+/// 限时模式过关时每剩余一秒奖励的分数
+pub const BONUS_POINTS_PER_SECOND: u32 = 20;
+/// 洗牌最多尝试的次数，都失败时游戏结束
+pub const SHUFFLE_ATTEMPTS: u32 = 50;
+// 有布局时生成初始棋盘最多尝试的次数（生成的棋盘可能没有可用移动，固定的格子还可能让某一格无色可选）
+const GENERATE_ATTEMPTS: u32 = 20;
+
+/// 游戏模式：决定一局以什么条件结束
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameMode {
+    Moves(u32),      // 限定步数
+    TimeAttack(f32), // 限定时间（秒）
+    Endless,         // 不限步数和时间
+}
+
+/// 棋盘尺寸、颜色数与游戏模式配置（可以序列化，缺少的字段取默认值）
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub width: usize,            // 列数
+    pub height: usize,           // 行数
+    pub colors: u8,              // 颜色数（宝石颜色值为 1..=colors）
+    pub mode: GameMode,          // 步数或时间限制
+    pub undo_limit: Option<u32>, // 一局最多撤销的次数（None=不限）
+    pub spawn: SpawnRule,        // 补充新宝石的策略
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            width: 8,
+            height: 8,
+            colors: 5,
+            mode: GameMode::Moves(30),
+            undo_limit: None,
+            spawn: SpawnRule::Uniform,
+        }
+    }
+}
+
+impl GameConfig {
+    /// 配置是否可用：棋盘至少 3x3，颜色数在 3..=MAX_COLORS 之间
+    pub fn is_valid(&self) -> bool {
+        self.width >= 3 && self.height >= 3 && (3..=MAX_COLORS).contains(&self.colors)
+    }
+}
+
+/// 过关目标，一局的所有目标都完成才算过关
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Objective {
+    Score(u32),                           // 得分达到指定分数
+    ClearColor { color: u8, count: u32 }, // 消除指定数量的某种颜色的宝石
+    ClearBlockers,                        // 清除棋盘上所有的障碍
+}
+
+/// 本局统计
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GameStats {
+    pub moves: u32,                                    // 已经走的步数
+    pub gems_cleared: u32,                             // 消除的宝石总数
+    pub specials_created: u32,                         // 生成的特殊宝石数
+    pub best_combo: u32,                               // 最长的连锁轮数
+    pub cleared_by_color: [u32; MAX_COLORS as usize], // 每种颜色消除的宝石数（下标为颜色值减一）
+    pub blockers_cleared: u32,                         // 清除的障碍数
+}
+
+/// 洗牌时一个宝石的移动
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shift {
+    pub from: (usize, usize), // 洗牌前的位置
+    pub to: (usize, usize),   // 洗牌后的位置
+    pub tile: Tile,           // 移动的宝石
+}
+
+/// 一个可用的移动：交换两个相邻方块后会产生的匹配
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: (usize, usize),     // 交换的第一个位置
+    pub to: (usize, usize),       // 交换的第二个位置（在 from 的右边或下边）
+    pub matches: Vec<MatchGroup>, // 交换后包含这两个位置的匹配组
+    pub special: bool,            // 是否为直接触发组合效果的特殊宝石交换
+}
+
+impl Move {
+    /// 粗略估计这步的基础得分（不含连锁），用于挑选提示
+    pub fn points(&self) -> u32 {
+        let combo = if self.special { MatchShape::Five.points() } else { 0 };
+        combo + self.matches.iter().map(|group| group.shape.points()).sum::<u32>()
+    }
+}
+
+// 撤销/重做用的快照：某一步之前稳定的棋盘和得分等状态
+// 限时模式的剩余时间不保存，撤销不会把时间还给玩家
+#[derive(Clone)]
+struct Snapshot {
+    board: Board,
+    score: u32,
+    moves_left: Option<u32>,
+    stats: GameStats,
+    rng: StdRng,
+}
+
+/// 游戏规则状态：棋盘、分数与胜负判定，不包含任何界面或动画数据
+#[derive(Clone)]
+pub struct Game {
+    config: GameConfig, // 棋盘尺寸与颜色数
+    board: Board,      // 游戏棋盘，存储每个位置的宝石
+    score: u32,        // 当前得分（累计分数）
+    objectives: Vec<Objective>, // 过关目标（全部完成即可获胜）
+    game_over: bool,   // 游戏是否结束（true=已结束，false=进行中）
+    moves_left: Option<u32>, // 剩余步数（None=不限步数）
+    time_left: Option<f32>,  // 剩余时间（秒，None=不限时间）
+    combo: u32,        // 当前这一步已经连锁消除的轮数（也是本轮得分倍数）
+    stats: GameStats,  // 本局统计
+    last_swap: Option<((usize, usize), (usize, usize))>, // 本步玩家交换的两个位置（用于决定特殊宝石生成的位置和方向）
+    seed: u64,         // 随机数种子（相同种子可以重现相同的棋盘和补充序列）
+    rng: StdRng,       // 棋盘生成与补充使用的随机数生成器
+    spawn: Arc<dyn SpawnPolicy>, // 补充新宝石时挑选颜色的策略
+    history: Vec<Snapshot>, // 每一步之前的快照（用于撤销）
+    future: Vec<Snapshot>,  // 被撤销的步骤（用于重做，走新的一步时清空）
+    undos_used: u32,        // 已经撤销的次数
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    /// 用随机种子创建新游戏
+    pub fn new() -> Self {
+        Self::with_seed(rand::thread_rng().gen())
+    }
+
+    /// 用指定种子和默认配置创建新游戏
+    ///
+    /// 相同种子总是生成相同的初始棋盘和补充序列，便于重现问题棋局。
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_config(GameConfig::default(), seed)
+    }
+
+    /// 用指定配置和种子创建新游戏，保证初始棋盘上没有三消，目标为达到 [`DEFAULT_TARGET_SCORE`] 分
+    ///
+    /// # Panics
+    ///
+    /// 配置不合法（见 [`GameConfig::is_valid`]）时 panic。
+    pub fn with_config(config: GameConfig, seed: u64) -> Self {
+        Self::with_layout(config, None, vec![Objective::Score(DEFAULT_TARGET_SCORE)], seed)
+    }
+
+    /// 按关卡创建新游戏：`layout` 中非空的格子固定不变，其余格子随机生成
+    ///
+    /// 初始棋盘上没有现成的三消，并且至少有一个可用移动。布局让某个空格放任何颜色都会连成三个时
+    /// （[`crate::Level`] 不接受这样的布局）无法满足这两点，游戏在开局时就结束。
+    ///
+    /// # Panics
+    ///
+    /// 配置不合法或布局尺寸与配置不一致时 panic（从文件读取的关卡由 [`crate::Level`] 事先检查）。
+    pub fn with_layout(config: GameConfig, layout: Option<&Board>, objectives: Vec<Objective>, seed: u64) -> Self {
+        assert!(config.is_valid(), "不支持的棋盘配置: {config:?}");
+        if let Some(layout) = layout {
+            assert!(
+                layout.width() == config.width && layout.height() == config.height,
+                "布局尺寸 {}x{} 与配置 {}x{} 不一致",
+                layout.width(),
+                layout.height(),
+                config.width,
+                config.height
+            );
+        }
+        let mut game = Game {
+            config,
+            board: Board::new(config.width, config.height),
+            score: 0,
+            objectives,
+            game_over: false,
+            moves_left: match config.mode {
+                GameMode::Moves(limit) => Some(limit),
+                _ => None,
+            },
+            time_left: match config.mode {
+                GameMode::TimeAttack(seconds) => Some(seconds),
+                _ => None,
+            },
+            combo: 0,
+            stats: GameStats::default(),
+            last_swap: None,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            spawn: config.spawn.policy(),
+            history: Vec::new(),
+            future: Vec::new(),
+            undos_used: 0,
+        };
+        // 生成的棋盘不能有现成的三消，还要保证至少有一个可用移动。没有固定的格子时总能生成出这样的棋盘
+        // （小棋盘颜色多时可能要生成很多次），一直重新生成；有布局时多次生成都不行就改为洗牌（固定的格子不参与）
+        let mut attempts = 0;
+        loop {
+            if game.generate_board(layout) && game.has_moves() {
+                return game;
+            }
+            attempts += 1;
+            if layout.is_some() && attempts >= GENERATE_ATTEMPTS {
+                break;
+            }
+        }
+        game.shuffle_gems(layout);
+        game
+    }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// 过关需要的分数（目标中没有分数要求时返回 None）
+    pub fn target_score(&self) -> Option<u32> {
+        self.objectives.iter().find_map(|objective| match *objective {
+            Objective::Score(score) => Some(score),
+            _ => None,
+        })
+    }
+
+    /// 过关目标
+    pub fn objectives(&self) -> &[Objective] {
+        &self.objectives
+    }
+
+    /// 某个目标的进度：(已完成的数量, 需要的数量)
+    pub fn objective_progress(&self, objective: &Objective) -> (u32, u32) {
+        match *objective {
+            Objective::Score(score) => (self.score.min(score), score),
+            Objective::ClearColor { color, count } => {
+                let index = (color as usize).checked_sub(1);
+                let cleared = index.and_then(|index| self.stats.cleared_by_color.get(index)).copied().unwrap_or(0);
+                (cleared.min(count), count)
+            }
+            Objective::ClearBlockers => {
+                let remaining = self.board.positions().filter(|&pos| self.board[pos].is_blocker()).count() as u32;
+                (self.stats.blockers_cleared, self.stats.blockers_cleared + remaining)
+            }
+        }
+    }
+
+    /// 本局使用的随机数种子
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// 剩余步数（None=不限步数）
+    pub fn moves_left(&self) -> Option<u32> {
+        self.moves_left
+    }
+
+    /// 剩余时间（秒，None=不限时间）
+    pub fn time_left(&self) -> Option<f32> {
+        self.time_left
+    }
+
+    /// 当前这一步已经连锁消除的轮数（第一次消除为 1），也是这一轮的得分倍数
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    /// 本局统计
+    pub fn stats(&self) -> &GameStats {
+        &self.stats
+    }
+
+    /// 过关奖励：剩余的步数或时间折算成的分数（没有过关时为 0）
+    pub fn bonus_points(&self) -> u32 {
+        if !self.is_won() {
+            return 0;
+        }
+        let moves = self.moves_left.unwrap_or(0) * BONUS_POINTS_PER_MOVE;
+        let time = self.time_left.unwrap_or(0.0) as u32 * BONUS_POINTS_PER_SECOND;
+        moves + time
+    }
+
+    /// 计入过关奖励的最终得分
+    pub fn final_score(&self) -> u32 {
+        self.score + self.bonus_points()
+    }
+
+    /// 步数或时间是否已经用完
+    pub fn is_out_of_turns(&self) -> bool {
+        self.moves_left == Some(0) || self.time_left == Some(0.0)
+    }
+
+    /// 让限时模式的时钟走过 `delta_time` 秒（游戏结束后不再计时）
+    pub fn tick(&mut self, delta_time: f32) {
+        if self.is_over() {
+            return;
+        }
+        if let Some(time) = &mut self.time_left {
+            *time = (*time - delta_time).max(0.0);
+        }
+    }
+
+    /// 是否已完成所有过关目标
+    pub fn is_won(&self) -> bool {
+        self.objectives.iter().all(|objective| {
+            let (done, needed) = self.objective_progress(objective);
+            done >= needed
+        })
+    }
+
+    /// 是否已失败：无法洗牌出可用移动，或步数/时间用完且连锁消除已经结束时仍未达到目标
+    pub fn is_lost(&self) -> bool {
+        if self.is_won() {
+            return false;
+        }
+        self.game_over || (self.is_out_of_turns() && self.is_settled())
+    }
+
+    /// 游戏是否结束（过关或失败）
+    pub fn is_over(&self) -> bool {
+        self.is_won() || self.is_lost()
+    }
+
+    /// 棋盘是否已稳定：没有空位，也没有待消除的匹配
+    pub fn is_settled(&self) -> bool {
+        !self.has_empty() && !self.has_pending_clears()
+    }
+
+    // 先放好布局中固定的格子，再按行优先顺序逐格生成其余格子：每格从放下后不会连成三个的颜色中随机挑选，
+    // 所以一次生成就没有现成的匹配（没有布局时每格最多排除左边和上边两种颜色，总有颜色可选）。
+    // 固定的格子和先生成的格子可能把某一格的所有颜色都排除掉，这时返回 false，棋盘上会有现成的匹配
+    fn generate_board(&mut self, layout: Option<&Board>) -> bool {
+        self.board = match layout {
+            Some(layout) => layout.clone(),
+            None => Board::new(self.config.width, self.config.height),
+        };
+        let random: Vec<_> = self.board.positions().filter(|&pos| self.board[pos].is_empty()).collect();
+        let mut clean = true;
+        for pos in random {
+            let choices: Vec<u8> = (1..=self.config.colors)
+                .filter(|&color| !self.board.would_match(pos, color))
+                .collect();
+            let color = match choices.choose(&mut self.rng) {
+                Some(&color) => color,
+                None => {
+                    clean = false;
+                    self.rng.gen_range(1..=self.config.colors)
+                }
+            };
+            self.board[pos] = Tile::gem(color);
+        }
+        clean
+    }
+
+    /// 查找棋盘上所有的匹配组
+    pub fn find_matches(&self) -> Vec<MatchGroup> {
+        self.board.find_matches()
+    }
+
+    /// 下一轮是否还有要消除的方块：匹配，或者等待再次炸开的包装宝石
+    pub fn has_pending_clears(&self) -> bool {
+        !self.find_matches().is_empty() || !self.primed_wrapped().is_empty()
+    }
+
+    /// 消除匹配的方块并计分，返回被清空的位置；没有匹配时返回空列表
+    ///
+    /// 直线四连会在交换位置（连锁时在连线上）留下条纹宝石，L/T 形会在交点留下包装宝石，
+    /// 直线五连留下彩色炸弹；玩家交换特殊宝石时先结算组合效果。被消除的特殊宝石会触发效果，
+    /// 波及范围内的特殊宝石也会依次触发。包装宝石第一次炸开后留在棋盘上，下一轮再炸一次。
+    /// 消除后棋盘上会留下空位，需要再调用 [`Game::apply_gravity`] 让方块下落。
+    pub fn remove_matches(&mut self) -> Vec<(usize, usize)> {
+        let groups = self.board.find_matches();
+        let primed = self.primed_wrapped();
+        let combo_hits = self.swap_combo();
+        if groups.is_empty() && primed.is_empty() && combo_hits.is_empty() {
+            return Vec::new();
+        }
+
+        // 记录连锁轮数，连锁越深得分倍数越高，大连锁奖励时间
+        self.combo += 1;
+        self.stats.best_combo = self.stats.best_combo.max(self.combo);
+        // 时间已经用完时不再奖励，避免结算连锁的过程中时钟又走起来
+        if self.combo >= CASCADE_BONUS_DEPTH {
+            if let Some(time) = self.time_left.as_mut().filter(|time| **time > 0.0) {
+                *time += CASCADE_BONUS_SECONDS;
+            }
+        }
+        let multiplier = self.combo;
+
+        let matched: BTreeSet<_> = groups.iter().flat_map(|group| group.cells.iter().copied()).collect();
+
+        // 每个匹配组按形状单独计分
+        self.score += multiplier * groups.iter().map(|group| group.shape.points()).sum::<u32>();
+
+        // 与匹配相邻的障碍被一起清除
+        let blockers: BTreeSet<_> = matched
+            .iter()
+            .flat_map(|&pos| self.board.neighbors(pos))
+            .filter(|&pos| self.board[pos].is_blocker())
+            .collect();
+
+        // 根据匹配形状生成特殊宝石
+        let spawned: Vec<_> = groups
+            .iter()
+            .filter_map(|group| {
+                let (pos, special) = match group.shape {
+                    MatchShape::Four => (self.spawn_position(group), self.striped_for(group)),
+                    MatchShape::L | MatchShape::T => (group.pivot?, Special::Wrapped),
+                    MatchShape::Five => (self.spawn_position(group), Special::ColorBomb),
+                    MatchShape::Three => return None,
+                };
+                let tile = match special {
+                    Special::ColorBomb => Tile::COLOR_BOMB,
+                    _ => Tile { color: group.color, special },
+                };
+                Some((pos, tile))
+            })
+            .collect();
+
+        // 触发被消除的特殊宝石（包括上一轮炸过的包装宝石和交换组合波及的位置），被波及的特殊宝石继续触发
+        let mut cleared: BTreeSet<_> = matched
+            .iter()
+            .chain(&primed)
+            .chain(&combo_hits)
+            .chain(&blockers)
+            .copied()
+            .collect();
+        let mut queue: Vec<_> = cleared.iter().copied().collect();
+        let mut survivors = Vec::new();
+        while let Some(pos) = queue.pop() {
+            if self.board[pos].special == Special::Wrapped {
+                survivors.push(pos);
+            }
+            for hit in self.board.blast_area(pos) {
+                if cleared.insert(hit) {
+                    queue.push(hit);
+                }
+            }
+        }
+        // 彩色炸弹（交换组合中用掉的已经提前清空，被波及的照常触发）和匹配的方块一样不按波及计分
+        let blasted = cleared
+            .iter()
+            .filter(|&pos| {
+                let tile = self.board[*pos];
+                !matched.contains(pos) && !tile.is_empty() && tile.special != Special::ColorBomb
+            })
+            .count();
+        self.score += multiplier * BLAST_POINTS_PER_TILE * blasted as u32;
+
+        // 第一次炸开的包装宝石留在原处，等下一轮再炸
+        let survivors: Vec<_> = survivors
+            .into_iter()
+            .map(|pos| (pos, Tile { color: self.board[pos].color, special: Special::WrappedPrimed }))
+            .collect();
+        let before = self.board.clone();
+        for &pos in &cleared {
+            self.board[pos] = Tile::EMPTY;
+        }
+        // 留下的和新生成的特殊宝石不算作被清空
+        for &(pos, tile) in survivors.iter().chain(&spawned) {
+            self.board[pos] = tile;
+            cleared.remove(&pos);
+        }
+        // 彩色炸弹不算作消除的宝石（交换组合中用掉的已经提前清空）
+        for &pos in &cleared {
+            let tile = before[pos];
+            if tile.is_empty() || tile.special == Special::ColorBomb {
+                continue;
+            }
+            if tile.is_blocker() {
+                self.stats.blockers_cleared += 1;
+            } else {
+                self.stats.gems_cleared += 1;
+                let index = (tile.color as usize).checked_sub(1);
+                if let Some(count) = index.and_then(|index| self.stats.cleared_by_color.get_mut(index)) {
+                    *count += 1;
+                }
+            }
+        }
+        self.stats.specials_created += spawned.len() as u32;
+        self.last_swap = None;
+
+        cleared.into_iter().collect()
+    }
+
+    // 玩家交换特殊宝石时的组合效果，返回需要清除的位置（不是特殊交换时返回空列表）
+    //
+    // 彩色炸弹 + 普通宝石：清除所有该颜色；彩色炸弹 + 条纹/包装：该颜色全部变成条纹/包装后一起触发；
+    // 彩色炸弹 + 彩色炸弹：清除整个棋盘；条纹 + 条纹：交换位置所在的整行和整列；
+    // 条纹 + 包装：以交换位置为中心的三行三列；包装 + 包装：以交换位置为中心的 5x5 区域。
+    fn swap_combo(&mut self) -> Vec<(usize, usize)> {
+        let Some((a, b)) = self.last_swap else {
+            return Vec::new();
+        };
+        let (tile_a, tile_b) = (self.board[a], self.board[b]);
+        if !is_special_swap(tile_a, tile_b) {
+            return Vec::new();
+        }
+
+        let is_striped = |special| matches!(special, Special::StripedH | Special::StripedV);
+        let is_wrapped = |special| matches!(special, Special::Wrapped | Special::WrappedPrimed);
+        let hits = match (tile_a.special, tile_b.special) {
+            (Special::ColorBomb, Special::ColorBomb) => self.board.positions().collect(),
+            (Special::ColorBomb, _) | (_, Special::ColorBomb) => {
+                let (bomb, other) = if tile_a.special == Special::ColorBomb {
+                    (a, tile_b)
+                } else {
+                    (b, tile_a)
+                };
+                let mut targets = self.board.positions_of_color(other.color);
+                if other.is_special() {
+                    for &pos in &targets {
+                        self.board[pos].special = if !is_striped(other.special) {
+                            other.special
+                        } else if self.rng.gen() {
+                            Special::StripedH
+                        } else {
+                            Special::StripedV
+                        };
+                    }
+                }
+                // 炸弹本身已经用掉，不再单独触发
+                self.board[bomb] = Tile::EMPTY;
+                targets.push(bomb);
+                return targets;
+            }
+            (x, y) if is_striped(x) && is_striped(y) => self.board.cross(b, 0),
+            (x, y) if is_wrapped(x) && is_wrapped(y) => {
+                let mut area = self.board.area(b, 2);
+                area.push(b);
+                area
+            }
+            _ => self.board.cross(b, 1),
+        };
+        // 参与组合的两个特殊宝石效果已经用掉，不再单独触发
+        self.board[a].special = Special::None;
+        self.board[b].special = Special::None;
+        if tile_a.special == Special::ColorBomb {
+            self.board[a] = Tile::EMPTY;
+        }
+        if tile_b.special == Special::ColorBomb {
+            self.board[b] = Tile::EMPTY;
+        }
+        hits
+    }
+
+    // 棋盘上已经炸过一次、等待再次炸开的包装宝石
+    fn primed_wrapped(&self) -> Vec<(usize, usize)> {
+        self.board
+            .positions()
+            .filter(|&pos| self.board[pos].special == Special::WrappedPrimed)
+            .collect()
+    }
+
+    // 特殊宝石的生成位置：优先使用玩家交换到连线上的位置，否则取连线上第一个普通宝石
+    fn spawn_position(&self, group: &MatchGroup) -> (usize, usize) {
+        if let Some((a, b)) = self.last_swap {
+            if let Some(&pos) = group.cells.iter().find(|&&pos| pos == b || pos == a) {
+                return pos;
+            }
+        }
+        group
+            .cells
+            .iter()
+            .copied()
+            .find(|&pos| !self.board[pos].is_special())
+            .unwrap_or(group.cells[0])
+    }
+
+    // 条纹方向：玩家横向交换生成横向条纹，纵向交换生成纵向条纹；连锁时按连线方向决定
+    fn striped_for(&self, group: &MatchGroup) -> Special {
+        let horizontal = match self.last_swap {
+            Some((a, b)) if group.cells.contains(&a) || group.cells.contains(&b) => a.0 == b.0,
+            _ => group.orientation == Orientation::Horizontal,
+        };
+        if horizontal {
+            Special::StripedH
+        } else {
+            Special::StripedV
+        }
+    }
+
+    /// 计算当前棋盘上每个方块需要下落的距离（不修改棋盘）
+    pub fn falls(&self) -> Vec<Fall> {
+        self.board.falls()
+    }
+
+    /// 让所有方块落到最下方的空位
+    pub fn apply_gravity(&mut self) {
+        self.board.apply_gravity();
+    }
+
+    /// 棋盘上是否还有空位
+    pub fn has_empty(&self) -> bool {
+        self.board.has_empty()
+    }
+
+    /// 按补充策略填充空白位置，返回新填充的位置
+    pub fn fill_empty(&mut self) -> Vec<(usize, usize)> {
+        let filled: Vec<_> = self
+            .board
+            .positions()
+            .filter(|&pos| self.board[pos].is_empty())
+            .collect();
+        for &pos in &filled {
+            // 自定义策略可能返回 0（会留下空格）或超出颜色数的值，限制到本局的颜色范围内
+            let color = self.spawn.pick(&self.board, pos, self.config.colors, &mut self.rng);
+            self.board[pos] = Tile::gem(color.clamp(1, self.config.colors));
+        }
+        filled
+    }
+
+    /// 换用自定义的补充策略（内置策略可以直接通过 [`GameConfig::spawn`] 选择）
+    pub fn set_spawn_policy(&mut self, policy: impl SpawnPolicy + 'static) {
+        self.spawn = Arc::new(policy);
+    }
+
+    /// 交换两个相邻的方块，不相邻或其中有障碍时返回 false
+    pub fn swap(&mut self, row1: usize, col1: usize, row2: usize, col2: usize) -> bool {
+        let (a, b) = ((row1, col1), (row2, col2));
+        if !is_adjacent(a, b) || self.board[a].is_blocker() || self.board[b].is_blocker() {
+            return false;
+        }
+
+        self.board.swap(a, b);
+
+        true
+    }
+
+    /// 尝试一次玩家移动：交换后若没有形成匹配则交换回来
+    ///
+    /// 彩色炸弹与任意宝石交换、或两个特殊宝石互相交换时，即使没有形成匹配也会生效。
+    /// 返回 true 表示交换已生效（消耗一步），接下来应调用 [`Game::remove_matches`]。
+    pub fn try_swap(&mut self, a: (usize, usize), b: (usize, usize)) -> bool {
+        let before = self.snapshot();
+        if self.is_over() || self.is_out_of_turns() || !self.swap(a.0, a.1, b.0, b.1) {
+            return false;
+        }
+        if !is_special_swap(self.board[a], self.board[b]) && self.find_matches().is_empty() {
+            // 没有匹配，交换回来
+            self.swap(a.0, a.1, b.0, b.1);
+            return false;
+        }
+        self.history.push(before);
+        self.future.clear();
+        if let Some(moves) = &mut self.moves_left {
+            *moves -= 1;
+        }
+        // 新的一步，重新开始计算连锁
+        self.combo = 0;
+        self.stats.moves += 1;
+        self.last_swap = Some((a, b));
+        true
+    }
+
+    /// 还能撤销的次数（None=不限）
+    pub fn undos_left(&self) -> Option<u32> {
+        self.config.undo_limit.map(|limit| limit.saturating_sub(self.undos_used))
+    }
+
+    /// 是否可以撤销：有走过的步、撤销次数没有用完、棋盘已经稳定且游戏没有结束
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty() && self.undos_left() != Some(0) && self.is_settled() && !self.is_over()
+    }
+
+    /// 是否可以重做被撤销的步骤（条件同撤销，但不消耗撤销次数）
+    pub fn can_redo(&self) -> bool {
+        !self.future.is_empty() && self.is_settled() && !self.is_over()
+    }
+
+    /// 撤销上一步，恢复到那一步之前的棋盘、分数、剩余步数和随机数状态
+    pub fn undo(&mut self) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        let Some(snapshot) = self.history.pop() else {
+            return false;
+        };
+        self.future.push(self.snapshot());
+        self.restore(snapshot);
+        self.undos_used += 1;
+        true
+    }
+
+    /// 重做上一次撤销的步骤
+    pub fn redo(&mut self) -> bool {
+        if !self.can_redo() {
+            return false;
+        }
+        let Some(snapshot) = self.future.pop() else {
+            return false;
+        };
+        self.history.push(self.snapshot());
+        self.restore(snapshot);
+        true
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            board: self.board.clone(),
+            score: self.score,
+            moves_left: self.moves_left,
+            stats: self.stats,
+            rng: self.rng.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.board = snapshot.board;
+        self.score = snapshot.score;
+        self.moves_left = snapshot.moves_left;
+        self.stats = snapshot.stats;
+        self.rng = snapshot.rng;
+        self.combo = 0;
+        self.last_swap = None;
+    }
+
+    /// 一次性结算所有连锁消除，直到棋盘稳定（无界面时使用）
+    pub fn settle(&mut self) {
+        loop {
+            self.apply_gravity();
+            self.fill_empty();
+            if self.remove_matches().is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// 检查是否有可用的移动
+    pub fn has_moves(&self) -> bool {
+        !self.find_moves().is_empty()
+    }
+
+    /// 列出所有可用的移动（会产生匹配的交换和特殊宝石交换），按位置的行优先顺序排列
+    pub fn find_moves(&self) -> Vec<Move> {
+        let (width, height) = (self.board.width(), self.board.height());
+        let mut test_board = self.board.clone();
+        let mut moves = Vec::new();
+        for (i, j) in self.board.positions() {
+            // 只检查向右和向下的交换，覆盖所有相邻对
+            for other in [(i, j + 1), (i + 1, j)] {
+                if other.0 >= height || other.1 >= width {
+                    continue;
+                }
+                if self.board[(i, j)].is_blocker() || self.board[other].is_blocker() {
+                    continue;
+                }
+                test_board.swap((i, j), other);
+                let matches: Vec<_> = test_board
+                    .find_matches()
+                    .into_iter()
+                    .filter(|group| group.cells.contains(&(i, j)) || group.cells.contains(&other))
+                    .collect();
+                test_board.swap((i, j), other);
+
+                let special = is_special_swap(self.board[(i, j)], self.board[other]);
+                if special || !matches.is_empty() {
+                    moves.push(Move {
+                        from: (i, j),
+                        to: other,
+                        matches,
+                        special,
+                    });
+                }
+            }
+        }
+        moves
+    }
+
+    /// 没有可用移动时洗牌：打乱棋盘上现有的普通宝石，特殊宝石和障碍留在原位
+    ///
+    /// 洗牌后保证没有现成的匹配并且至少有一个可用移动。成功时返回每个宝石的移动；
+    /// 尝试 [`SHUFFLE_ATTEMPTS`] 次仍然无解时游戏结束，返回 None。
+    pub fn reshuffle(&mut self) -> Option<Vec<Shift>> {
+        self.shuffle_gems(None)
+    }
+
+    // 洗牌的实现；`fixed` 中非空的格子（关卡布局）也留在原位
+    fn shuffle_gems(&mut self, fixed: Option<&Board>) -> Option<Vec<Shift>> {
+        let gems: Vec<_> = self
+            .board
+            .positions()
+            .filter(|&pos| {
+                let tile = self.board[pos];
+                !tile.is_empty() && !tile.is_special() && !tile.is_blocker() && fixed.is_none_or(|fixed| fixed[pos].is_empty())
+            })
+            .map(|pos| (pos, self.board[pos]))
+            .collect();
+
+        for _ in 0..SHUFFLE_ATTEMPTS {
+            let mut pool = gems.clone();
+            pool.shuffle(&mut self.rng);
+            let mut board = self.board.clone();
+            for &(pos, _) in &gems {
+                board[pos] = Tile::EMPTY;
+            }
+
+            // 按顺序给每个格子挑一个放下后不会连成三个的宝石，挑不到就重新打乱
+            let mut shifts = Vec::with_capacity(gems.len());
+            for &(to, _) in &gems {
+                let Some(index) = pool.iter().position(|&(_, tile)| !board.would_match(to, tile.color)) else {
+                    break;
+                };
+                let (from, tile) = pool.swap_remove(index);
+                board[to] = tile;
+                shifts.push(Shift { from, to, tile });
+            }
+            if !pool.is_empty() {
+                continue;
+            }
+
+            let before = std::mem::replace(&mut self.board, board);
+            if self.has_moves() {
+                return Some(shifts);
+            }
+            self.board = before;
+        }
+        self.game_over = true;
+        None
+    }
+}
+
+// 两个宝石交换时是否直接触发组合效果：彩色炸弹与任意宝石，或两个特殊宝石
+fn is_special_swap(a: Tile, b: Tile) -> bool {
+    let bomb = |tile: Tile, other: Tile| tile.special == Special::ColorBomb && !other.is_empty() && !other.is_blocker();
+    bomb(a, b) || bomb(b, a) || (a.is_special() && b.is_special())
+}
+
+// 两个位置是否上下或左右相邻
+fn is_adjacent(a: (usize, usize), b: (usize, usize)) -> bool {
+    a.0.abs_diff(b.0) + a.1.abs_diff(b.1) == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::tests::board;
+
+    // 用字符串画出的棋盘开局（尺寸取自棋盘，其余配置取默认值）
+    fn game(rows: &[&str]) -> Game {
+        let board = board(rows);
+        let config = GameConfig {
+            width: board.width(),
+            height: board.height(),
+            ..GameConfig::default()
+        };
+        let mut game = Game::with_config(config, 1);
+        game.board = board;
+        game
+    }
+
+    // 不限步数、目标分数达不到的游戏，可以一直走下去
+    fn endless(seed: u64) -> Game {
+        let config = GameConfig {
+            mode: GameMode::Endless,
+            ..GameConfig::default()
+        };
+        Game::with_layout(config, None, vec![Objective::Score(u32::MAX)], seed)
+    }
+
+    // 每一步走第一个可用移动并结算连锁，记录初始棋盘和每一步之后的棋盘
+    fn play(seed: u64, steps: usize) -> Vec<Board> {
+        let mut game = endless(seed);
+        let mut boards = vec![game.board().clone()];
+        for _ in 0..steps {
+            let Some(first) = game.find_moves().into_iter().next() else {
+                break;
+            };
+            assert!(game.try_swap(first.from, first.to));
+            game.settle();
+            boards.push(game.board().clone());
+        }
+        boards
+    }
+
+    #[test]
+    fn same_seed_same_boards_and_refills() {
+        for seed in [0, 1, 42, u64::MAX] {
+            assert_eq!(play(seed, 10), play(seed, 10));
+        }
+        assert_ne!(play(1, 0), play(2, 0));
+        assert_eq!(Game::with_seed(7).seed(), 7);
+    }
+
+    #[test]
+    fn last_move_loses_only_after_cascade_settles() {
+        let mut game = game(&["121", "212", "343"]);
+        game.moves_left = Some(1);
+        assert!(game.try_swap((0, 1), (1, 1)));
+        assert_eq!(game.moves_left(), Some(0));
+        assert!(game.is_out_of_turns());
+        // 最后一步的连锁还没结算完，不算失败
+        assert!(!game.is_lost());
+        assert!(!game.remove_matches().is_empty());
+        assert!(!game.is_lost());
+
+        game.settle();
+        assert!(game.is_lost());
+        assert!(game.is_over());
+        assert!(!game.try_swap((2, 0), (2, 1)));
+    }
+
+    #[test]
+    fn reaching_the_target_on_the_last_move_wins() {
+        let mut game = game(&["121", "212", "343"]);
+        game.moves_left = Some(1);
+        game.objectives = vec![Objective::Score(100)];
+        assert!(game.try_swap((0, 1), (1, 1)));
+        game.settle();
+        assert!(game.is_won());
+        assert!(!game.is_lost());
+    }
+
+    // 限时模式的开局
+    fn timed(rows: &[&str], seconds: f32) -> Game {
+        let mut game = game(rows);
+        game.moves_left = None;
+        game.time_left = Some(seconds);
+        game
+    }
+
+    #[test]
+    fn clock_runs_out_into_a_loss() {
+        let mut game = timed(&["123", "231", "312"], 1.0);
+        game.tick(0.4);
+        assert_eq!(game.time_left(), Some(0.6));
+        assert!(!game.is_over());
+        game.tick(5.0);
+        assert_eq!(game.time_left(), Some(0.0));
+        assert!(game.is_out_of_turns());
+        assert!(game.is_lost());
+        assert!(!game.try_swap((0, 0), (0, 1)));
+    }
+
+    #[test]
+    fn deep_cascades_award_bonus_seconds() {
+        let mut game = timed(&["111", "234", "342"], 10.0);
+        game.combo = CASCADE_BONUS_DEPTH - 2;
+        game.remove_matches();
+        assert_eq!(game.time_left(), Some(10.0));
+
+        game.board = board(&["222", "314", "431"]);
+        game.remove_matches();
+        assert_eq!(game.combo(), CASCADE_BONUS_DEPTH);
+        assert_eq!(game.time_left(), Some(10.0 + CASCADE_BONUS_SECONDS));
+    }
+
+    #[test]
+    fn bonus_seconds_do_not_revive_an_expired_clock() {
+        let mut game = timed(&["111", "234", "342"], 1.0);
+        game.tick(1.0);
+        game.combo = CASCADE_BONUS_DEPTH;
+        game.remove_matches();
+        assert_eq!(game.time_left(), Some(0.0));
+    }
+
+    #[test]
+    fn four_in_a_line_leaves_striped_at_swap() {
+        let mut game = game(&["1121", "3313", "4242"]);
+        assert!(game.try_swap((0, 2), (1, 2)));
+        let cleared = game.remove_matches();
+        assert_eq!(cleared, vec![(0, 0), (0, 1), (0, 3)]);
+        assert_eq!(game.board()[(0, 2)], Tile { color: 1, special: Special::StripedV });
+        assert_eq!(game.score(), MatchShape::Four.points());
+        assert_eq!(game.stats().specials_created, 1);
+    }
+
+    #[test]
+    fn striped_blast_scores_extra_tiles() {
+        let mut game = game(&["23423", "11142", "34234"]);
+        game.board[(1, 0)].special = Special::StripedV;
+        let cleared = game.remove_matches();
+        assert_eq!(cleared, vec![(0, 0), (1, 0), (1, 1), (1, 2), (2, 0)]);
+        assert_eq!(game.score(), 100 + 2 * BLAST_POINTS_PER_TILE);
+        assert_eq!(game.stats().gems_cleared, 5);
+    }
+
+    #[test]
+    fn l_shape_leaves_wrapped_at_corner() {
+        let mut game = game(&["1234", "1342", "1114"]);
+        game.remove_matches();
+        assert_eq!(game.board()[(2, 0)], Tile { color: 1, special: Special::Wrapped });
+        assert_eq!(game.score(), MatchShape::L.points());
+        assert_eq!(game.stats().specials_created, 1);
+    }
+
+    #[test]
+    fn wrapped_explodes_twice() {
+        let mut game = game(&["2323", "1113", "3232"]);
+        game.board[(1, 1)].special = Special::Wrapped;
+        let cleared = game.remove_matches();
+        assert_eq!(cleared.len(), 8);
+        assert_eq!(game.score(), 100 + 6 * BLAST_POINTS_PER_TILE);
+        // 炸过一次的包装宝石留在原处，落下后再炸一次
+        assert_eq!(game.board()[(1, 1)], Tile { color: 1, special: Special::WrappedPrimed });
+        assert!(game.has_pending_clears());
+        game.apply_gravity();
+        assert!(game.remove_matches().contains(&(2, 1)));
+        assert!(game.board()[(2, 1)].is_empty());
+        assert!(!game.has_pending_clears());
+    }
+
+    #[test]
+    fn color_bomb_swap_clears_color_without_counting_bomb() {
+        let mut game = game(&["*23", "314", "231"]);
+        assert!(game.try_swap((0, 0), (0, 1)));
+        let cleared = game.remove_matches();
+        assert_eq!(cleared, vec![(0, 0), (0, 1), (2, 0)]);
+        assert_eq!(game.score(), 2 * BLAST_POINTS_PER_TILE);
+        assert_eq!(game.stats().gems_cleared, 2);
+        assert_eq!(game.stats().cleared_by_color[1], 2);
+    }
+
+    #[test]
+    fn color_bomb_hit_by_blast_fires_without_counting_bomb() {
+        let mut game = game(&["*323", "1113", "3232"]);
+        game.board[(1, 0)].special = Special::StripedV;
+        // 条纹宝石波及彩色炸弹，炸弹清除数量最多的颜色 3
+        let cleared = game.remove_matches();
+        assert_eq!(cleared.len(), 9);
+        assert!(game.board().positions_of_color(3).is_empty());
+        assert_eq!(game.score(), 100 + 5 * BLAST_POINTS_PER_TILE);
+        assert_eq!(game.stats().gems_cleared, 8);
+    }
+
+    #[test]
+    fn two_color_bombs_clear_the_board() {
+        let mut game = game(&["**3", "123", "231"]);
+        assert!(game.try_swap((0, 0), (0, 1)));
+        assert_eq!(game.remove_matches().len(), 9);
+        assert!(game.board().positions().all(|pos| game.board()[pos].is_empty()));
+        assert_eq!(game.score(), 7 * BLAST_POINTS_PER_TILE);
+        assert_eq!(game.stats().gems_cleared, 7);
+    }
+
+    #[test]
+    fn each_group_scores_by_shape() {
+        let mut game = game(&["1112", "2343", "4444"]);
+        assert_eq!(game.remove_matches().len(), 6);
+        assert_eq!(game.score(), MatchShape::Three.points() + MatchShape::Four.points());
+    }
+
+    #[test]
+    fn cascade_multiplies_score() {
+        let mut game = game(&["111", "234", "342"]);
+        assert_eq!(game.remove_matches().len(), 3);
+        assert_eq!((game.combo(), game.score()), (1, 100));
+
+        // 下一轮连锁得分翻倍
+        game.board = board(&["222", "314", "431"]);
+        game.remove_matches();
+        assert_eq!((game.combo(), game.score()), (2, 300));
+        assert_eq!(game.stats().best_combo, 2);
+        assert!(game.remove_matches().is_empty());
+        assert_eq!(game.score(), 300);
+
+        // 新的一步重新开始计算连锁
+        game.board = board(&["121", "212", "343"]);
+        assert!(game.try_swap((0, 1), (1, 1)));
+        assert_eq!(game.combo(), 0);
+        game.remove_matches();
+        assert_eq!(game.combo(), 1);
+        assert_eq!(game.stats().best_combo, 2);
+    }
+
+    #[test]
+    fn find_moves_lists_matching_swaps_in_order() {
+        let game = game(&["1121", "2343", "4232"]);
+        let moves = game.find_moves();
+        let pairs: Vec<_> = moves.iter().map(|m| (m.from, m.to)).collect();
+        assert_eq!(pairs, vec![((0, 2), (0, 3)), ((1, 2), (2, 2))]);
+        assert_eq!(moves[0].matches.len(), 1);
+        assert_eq!(moves[0].matches[0].cells, vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(moves[1].matches[0].cells, vec![(1, 1), (1, 2), (1, 3)]);
+        assert!(moves.iter().all(|m| !m.special && m.points() == MatchShape::Three.points()));
+        // 查找移动不会改动棋盘
+        assert_eq!(game.board(), &board(&["1121", "2343", "4232"]));
+        assert!(game.has_moves());
+    }
+
+    #[test]
+    fn find_moves_includes_special_swaps_and_skips_blockers() {
+        let moves = game(&["12#", "3*4", "425"]).find_moves();
+        let pairs: Vec<_> = moves.iter().map(|m| (m.from, m.to)).collect();
+        assert_eq!(pairs, vec![((0, 1), (1, 1)), ((1, 0), (1, 1)), ((1, 1), (1, 2)), ((1, 1), (2, 1))]);
+        assert!(moves.iter().all(|m| m.special && m.matches.is_empty()));
+        assert!(moves[0].points() > MatchShape::Four.points());
+
+        assert!(!game(&["123", "231", "312"]).has_moves());
+    }
+
+    // 走第一个可用移动并结算连锁
+    fn play_first_move(game: &mut Game) {
+        let first = game.find_moves().into_iter().next().expect("没有可用移动");
+        assert!(game.try_swap(first.from, first.to));
+        assert!(!game.can_undo());
+        game.settle();
+    }
+
+    #[test]
+    fn undo_and_redo_restore_board_score_and_rng() {
+        let mut game = endless(3);
+        let start = game.board().clone();
+        play_first_move(&mut game);
+        let (after, score) = (game.board().clone(), game.score());
+        assert!(score > 0);
+
+        assert!(game.undo());
+        assert_eq!((game.board(), game.score(), game.stats().moves), (&start, 0, 0));
+        assert!(game.can_redo());
+        assert!(game.redo());
+        assert_eq!((game.board(), game.score(), game.stats().moves), (&after, score, 1));
+
+        // 撤销后走同一步，补充的宝石和之前一样
+        assert!(game.undo());
+        play_first_move(&mut game);
+        assert_eq!(game.board(), &after);
+        assert!(!game.can_redo());
+    }
+
+    #[test]
+    fn undo_budget_is_spent_and_restores_moves() {
+        let config = GameConfig {
+            mode: GameMode::Moves(10),
+            undo_limit: Some(1),
+            ..GameConfig::default()
+        };
+        let mut game = Game::with_layout(config, None, vec![Objective::Score(u32::MAX)], 5);
+        assert!(!game.can_undo());
+        play_first_move(&mut game);
+        assert_eq!(game.moves_left(), Some(9));
+        assert!(game.undo());
+        assert_eq!(game.moves_left(), Some(10));
+        assert_eq!(game.undos_left(), Some(0));
+
+        play_first_move(&mut game);
+        assert!(!game.can_undo());
+        assert!(!game.undo());
+        assert_eq!(game.moves_left(), Some(9));
+    }
+
+    #[test]
+    fn reshuffle_leaves_no_matches_and_a_move() {
+        for seed in 0..20 {
+            let mut game = Game::with_seed(seed);
+            game.board[(0, 0)] = Tile::BLOCKER;
+            game.board[(4, 4)].special = Special::Wrapped;
+            let before = game.board().clone();
+            let colors = |board: &Board| {
+                let mut colors: Vec<_> = board.positions().map(|pos| board[pos].color).collect();
+                colors.sort_unstable();
+                colors
+            };
+
+            let shifts = game.reshuffle().expect("洗牌失败");
+            assert!(game.find_matches().is_empty());
+            assert!(game.has_moves());
+            assert!(!game.is_over());
+            // 宝石只是换了位置，障碍和特殊宝石留在原位
+            assert_eq!(colors(game.board()), colors(&before));
+            assert_eq!(game.board()[(0, 0)], Tile::BLOCKER);
+            assert_eq!(game.board()[(4, 4)], before[(4, 4)]);
+            for shift in shifts {
+                assert_eq!(before[shift.from], shift.tile);
+                assert_eq!(game.board()[shift.to], shift.tile);
+            }
+        }
+    }
+
+    #[test]
+    fn failed_reshuffle_ends_the_game() {
+        // 宝石被障碍隔开，怎么排都没有可用移动
+        let mut game = game(&["1#2", "###", "2#1"]);
+        assert_eq!(game.reshuffle(), None);
+        assert!(game.is_lost());
+    }
+
+    #[test]
+    fn generated_boards_have_no_matches_and_a_move() {
+        for (width, height, colors) in [(8, 8, 5), (7, 7, 4), (5, 9, 5), (8, 8, 6), (10, 6, 3), (4, 4, 3)] {
+            for seed in 0..50 {
+                let config = GameConfig {
+                    width,
+                    height,
+                    colors,
+                    ..GameConfig::default()
+                };
+                let game = Game::with_config(config, seed);
+                assert!(game.find_matches().is_empty(), "{config:?} 种子 {seed}");
+                assert!(game.has_moves(), "{config:?} 种子 {seed}");
+                assert!(!game.is_over());
+                assert!(game.board().positions().all(|pos| (1..=colors).contains(&game.board()[pos].color)));
+            }
+        }
+    }
+
+    #[test]
+    fn small_board_with_many_colors_always_starts_playable() {
+        let config = GameConfig {
+            width: 3,
+            height: 3,
+            colors: MAX_COLORS,
+            ..GameConfig::default()
+        };
+        for seed in 0..500 {
+            let game = Game::with_config(config, seed);
+            assert!(game.has_moves() && !game.is_over(), "种子 {seed}");
+            assert!(game.find_matches().is_empty());
+        }
+    }
+
+    #[test]
+    fn match_clears_adjacent_blockers() {
+        let mut game = game(&["#23", "111", "324"]);
+        let cleared = game.remove_matches();
+        assert!(cleared.contains(&(0, 0)));
+        assert_eq!(game.stats().blockers_cleared, 1);
+        assert_eq!(game.stats().gems_cleared, 3);
+        assert_eq!(game.score(), 100 + BLAST_POINTS_PER_TILE);
+    }
+
+    #[test]
+    fn layout_cells_stay_fixed() {
+        let layout = board(&[
+            "1#......", "2#......", "........", "........", "........", "........", "........", "...*....",
+        ]);
+        for seed in 0..20 {
+            let game = Game::with_layout(GameConfig::default(), Some(&layout), vec![Objective::ClearBlockers], seed);
+            for pos in layout.positions().filter(|&pos| !layout[pos].is_empty()) {
+                assert_eq!(game.board()[pos], layout[pos]);
+            }
+            assert!(game.has_moves());
+        }
+    }
+
+    #[test]
+    fn tight_layout_never_starts_with_matches() {
+        // (2, 2) 只能放颜色 3，上面两格先生成，都是 3 时这一格就没有颜色可选
+        let layout = board(&[".....", ".....", "11.22", ".....", "....."]);
+        let config = GameConfig {
+            width: 5,
+            height: 5,
+            colors: 3,
+            ..GameConfig::default()
+        };
+        for seed in 0..50 {
+            let game = Game::with_layout(config, Some(&layout), vec![Objective::Score(1000)], seed);
+            assert!(game.find_matches().is_empty(), "种子 {seed}");
+            assert!(game.has_moves());
+            assert!(!game.is_over());
+            assert_eq!(game.board()[(2, 2)], Tile::gem(3));
+        }
+    }
+
+    #[test]
+    fn winning_ends_the_game_with_a_bonus_for_moves_left() {
+        let mut game = game(&["121", "212", "343"]);
+        game.moves_left = Some(5);
+        game.objectives = vec![Objective::Score(100)];
+        assert_eq!(game.bonus_points(), 0);
+        assert!(game.try_swap((0, 1), (1, 1)));
+        game.settle();
+        assert!(game.is_won() && game.is_over());
+        assert!(!game.try_swap((2, 0), (2, 1)));
+        assert_eq!(game.bonus_points(), 4 * BONUS_POINTS_PER_MOVE);
+        assert_eq!(game.final_score(), game.score() + 4 * BONUS_POINTS_PER_MOVE);
+    }
+
+    #[test]
+    fn time_attack_bonus_counts_whole_seconds_left() {
+        let mut game = timed(&["121", "212", "343"], 12.5);
+        game.objectives = vec![Objective::Score(100)];
+        assert!(game.try_swap((0, 1), (1, 1)));
+        game.settle();
+        game.tick(1.0);
+        assert_eq!(game.time_left(), Some(12.5));
+        assert_eq!(game.bonus_points(), 12 * BONUS_POINTS_PER_SECOND);
+    }
+
+    #[test]
+    fn endless_game_ends_when_objectives_are_met() {
+        let mut game = game(&["121", "212", "343"]);
+        game.moves_left = None;
+        game.objectives = vec![Objective::Score(100)];
+        assert!(game.try_swap((0, 1), (1, 1)));
+        game.settle();
+        assert!(game.is_over());
+    }
+}
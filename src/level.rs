@@ -0,0 +1,542 @@
+//! 关卡文件：棋盘尺寸、初始布局、步数或时间限制、过关目标、星级分数和补充规则
+//!
+//! 同样的内容可以写成 RON、JSON 或 TOML，按文件扩展名（`.ron`/`.json`/`.toml`）选择格式。RON 示例：
+//!
+//! ```text
+//! (
+//!     name: "冰封小径",
+//!     width: 6,
+//!     height: 6,
+//!     colors: 4,
+//!     moves: Some(20),
+//!     objectives: [Score(1500), ClearColor(color: 2, count: 15), ClearBlockers],
+//!     stars: [1500, 2500, 4000],
+//!     layout: Some([
+//!         "......",
+//!         ".#..#.",
+//!         "......",
+//!         "..11..",
+//!         ".#..#.",
+//!         "......",
+//!     ]),
+//!     spawn: NoFreeMatches,
+//! )
+//! ```
+//!
+//! 布局中每个字符是一个格子：`.` 随机生成，`#` 障碍，`1`-`8` 指定颜色的宝石，`*` 彩色炸弹。
+//! 固定的宝石不能已经连成三个，也不能让某个随机生成的格子放任何颜色都连成三个。
+//! 省略 `moves` 和 `time` 时不限步数和时间，省略 `layout` 时整个棋盘随机生成。
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::board::Board;
+use crate::game::{Game, GameConfig, GameMode, Objective, MAX_COLORS};
+use crate::spawn::SpawnRule;
+use crate::tile::Tile;
+
+/// 关卡文件的格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelFormat {
+    Ron,
+    Json,
+    Toml,
+}
+
+impl LevelFormat {
+    /// 根据文件扩展名判断格式
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ron" => Some(LevelFormat::Ron),
+            "json" => Some(LevelFormat::Json),
+            "toml" => Some(LevelFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LevelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LevelFormat::Ron => "RON",
+            LevelFormat::Json => "JSON",
+            LevelFormat::Toml => "TOML",
+        })
+    }
+}
+
+/// 读取或检查关卡时的错误（行号和列号从 1 开始）
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),                                          // 读取文件失败
+    UnknownFormat(PathBuf),                                      // 无法从扩展名判断格式
+    Parse { format: LevelFormat, message: String },              // 文件内容不符合格式或缺少字段
+    BoardSize { width: usize, height: usize },                   // 棋盘小于 3x3
+    Colors(u8),                                                  // 颜色数不在 3..=MAX_COLORS 之间
+    ConflictingLimits,                                           // 同时限定了步数和时间
+    ZeroLimit,                                                   // 步数为 0 或时间不是正数
+    LayoutRows { expected: usize, found: usize },                // 布局行数与高度不一致
+    LayoutColumns { row: usize, expected: usize, found: usize }, // 布局某一行的长度与宽度不一致
+    UnknownCell { row: usize, col: usize, code: char },          // 无法识别的格子字符
+    CellColor { row: usize, col: usize, color: u8, colors: u8 }, // 格子的颜色超出本关的颜色数
+    LayoutMatch { row: usize, col: usize },                      // 固定的格子已经连成三个
+    LayoutNoColor { row: usize, col: usize },                    // 随机生成的格子放任何颜色都会和固定的宝石连成三个
+    NoObjectives,                                                // 没有过关目标
+    Objective { index: usize, reason: String },                  // 无法完成的过关目标（序号从 1 开始）
+    Stars(Vec<u32>),                                             // 星级分数不是三个递增的正数
+    SpawnWeights(String),                                        // 补充权重不合法
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Io(err) => write!(f, "无法读取关卡文件: {err}"),
+            LevelError::UnknownFormat(path) => {
+                write!(f, "无法识别关卡文件格式（需要 .ron、.json 或 .toml）: {}", path.display())
+            }
+            LevelError::Parse { format, message } => write!(f, "{format} 格式错误: {message}"),
+            LevelError::BoardSize { width, height } => write!(f, "棋盘 {width}x{height} 太小，至少需要 3x3"),
+            LevelError::Colors(colors) => write!(f, "颜色数 {colors} 不在 3 到 {MAX_COLORS} 之间"),
+            LevelError::ConflictingLimits => write!(f, "不能同时限定步数（moves）和时间（time）"),
+            LevelError::ZeroLimit => write!(f, "步数和时间限制必须大于 0"),
+            LevelError::LayoutRows { expected, found } => {
+                write!(f, "布局有 {found} 行，但棋盘高度为 {expected}")
+            }
+            LevelError::LayoutColumns { row, expected, found } => {
+                write!(f, "布局第 {row} 行有 {found} 个格子，但棋盘宽度为 {expected}")
+            }
+            LevelError::UnknownCell { row, col, code } => {
+                write!(f, "布局第 {row} 行第 {col} 列的字符 '{code}' 无法识别")
+            }
+            LevelError::CellColor { row, col, color, colors } => {
+                write!(f, "布局第 {row} 行第 {col} 列的颜色 {color} 超出本关的 {colors} 种颜色")
+            }
+            LevelError::LayoutMatch { row, col } => {
+                write!(f, "布局第 {row} 行第 {col} 列的固定宝石已经连成三个")
+            }
+            LevelError::LayoutNoColor { row, col } => {
+                write!(f, "布局第 {row} 行第 {col} 列的空格放任何颜色都会和固定的宝石连成三个")
+            }
+            LevelError::NoObjectives => write!(f, "关卡至少需要一个过关目标"),
+            LevelError::Objective { index, reason } => write!(f, "第 {index} 个过关目标无法完成: {reason}"),
+            LevelError::Stars(stars) => write!(f, "星级分数 {stars:?} 必须是三个递增的正数"),
+            LevelError::SpawnWeights(reason) => write!(f, "补充权重不合法: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LevelError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LevelError {
+    fn from(err: std::io::Error) -> Self {
+        LevelError::Io(err)
+    }
+}
+
+/// 检查通过的关卡
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub name: String,               // 关卡名
+    pub config: GameConfig,         // 棋盘尺寸、颜色数、步数或时间限制和补充规则
+    pub layout: Option<Board>,      // 初始布局，空格子随机生成（None=全部随机）
+    pub objectives: Vec<Objective>, // 过关目标
+    pub stars: [u32; 3],            // 获得一、二、三星需要的分数
+    pub seed: Option<u64>,          // 固定的随机数种子（None=每次随机）
+}
+
+impl Level {
+    /// 读取关卡文件，按扩展名选择格式
+    pub fn load(path: impl AsRef<Path>) -> Result<Level, LevelError> {
+        let path = path.as_ref();
+        let format = LevelFormat::from_path(path).ok_or_else(|| LevelError::UnknownFormat(path.to_owned()))?;
+        let text = std::fs::read_to_string(path)?;
+        Level::parse(&text, format)
+    }
+
+    /// 解析并检查关卡内容
+    pub fn parse(text: &str, format: LevelFormat) -> Result<Level, LevelError> {
+        let parse_error = |message: String| LevelError::Parse { format, message };
+        let file: LevelFile = match format {
+            LevelFormat::Ron => ron::from_str(text).map_err(|err| parse_error(err.to_string()))?,
+            LevelFormat::Json => serde_json::from_str(text).map_err(|err| parse_error(err.to_string()))?,
+            LevelFormat::Toml => toml::from_str(text).map_err(|err| parse_error(err.to_string()))?,
+        };
+        file.validate()
+    }
+
+    /// 按得分（通常是计入过关奖励的 [`Game::final_score`]）计算星级（0 到 3）
+    pub fn stars_for(&self, score: u32) -> u32 {
+        self.stars.iter().filter(|&&threshold| score >= threshold).count() as u32
+    }
+
+    /// 开始这一关：关卡指定了种子时使用该种子，否则随机
+    pub fn start(&self) -> Game {
+        self.start_with_seed(self.seed.unwrap_or_else(rand::random))
+    }
+
+    /// 用指定种子开始这一关
+    pub fn start_with_seed(&self, seed: u64) -> Game {
+        Game::with_layout(self.config, self.layout.as_ref(), self.objectives.clone(), seed)
+    }
+}
+
+// 关卡文件的原始内容，检查后转换为 Level
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LevelFile {
+    name: String,
+    width: usize,
+    height: usize,
+    colors: u8,
+    #[serde(default)]
+    moves: Option<u32>,
+    #[serde(default)]
+    time: Option<f32>,
+    #[serde(default)]
+    undo_limit: Option<u32>,
+    #[serde(default)]
+    layout: Option<Vec<String>>,
+    objectives: Vec<ObjectiveFile>,
+    stars: Vec<u32>,
+    #[serde(default)]
+    spawn: SpawnFile,
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+enum ObjectiveFile {
+    Score(u32),
+    ClearColor { color: u8, count: u32 },
+    ClearBlockers,
+}
+
+#[derive(Default, Deserialize)]
+enum SpawnFile {
+    #[default]
+    Uniform,
+    Weighted(Vec<u32>),
+    NoFreeMatches,
+    Generous(f64),
+}
+
+impl LevelFile {
+    fn validate(self) -> Result<Level, LevelError> {
+        let (width, height, colors) = (self.width, self.height, self.colors);
+        if width < 3 || height < 3 {
+            return Err(LevelError::BoardSize { width, height });
+        }
+        if !(3..=MAX_COLORS).contains(&colors) {
+            return Err(LevelError::Colors(colors));
+        }
+
+        let mode = match (self.moves, self.time) {
+            (Some(_), Some(_)) => return Err(LevelError::ConflictingLimits),
+            (Some(0), None) => return Err(LevelError::ZeroLimit),
+            (Some(moves), None) => GameMode::Moves(moves),
+            (None, Some(time)) if !(time > 0.0 && time.is_finite()) => return Err(LevelError::ZeroLimit),
+            (None, Some(time)) => GameMode::TimeAttack(time),
+            (None, None) => GameMode::Endless,
+        };
+
+        let layout = match &self.layout {
+            Some(rows) => Some(parse_layout(rows, width, height, colors)?),
+            None => None,
+        };
+        let has_blockers = layout
+            .as_ref()
+            .is_some_and(|layout| layout.positions().any(|pos| layout[pos].is_blocker()));
+
+        let spawn = match self.spawn {
+            SpawnFile::Uniform => SpawnRule::Uniform,
+            SpawnFile::NoFreeMatches => SpawnRule::NoFreeMatches,
+            SpawnFile::Generous(chance) if !(0.0..=1.0).contains(&chance) => {
+                return Err(LevelError::SpawnWeights(format!("概率 {chance} 不在 0 到 1 之间")));
+            }
+            SpawnFile::Generous(chance) => SpawnRule::Generous(chance),
+            SpawnFile::Weighted(weights) => {
+                if weights.len() > colors as usize {
+                    return Err(LevelError::SpawnWeights(format!(
+                        "给出了 {} 个权重，但本关只有 {colors} 种颜色",
+                        weights.len()
+                    )));
+                }
+                // 没有给出的颜色权重为 1
+                let mut table = [1; MAX_COLORS as usize];
+                table[..weights.len()].copy_from_slice(&weights);
+                if table[..colors as usize].iter().all(|&weight| weight == 0) {
+                    return Err(LevelError::SpawnWeights("所有颜色的权重都为 0".to_owned()));
+                }
+                SpawnRule::Weighted(table)
+            }
+        };
+
+        if self.objectives.is_empty() {
+            return Err(LevelError::NoObjectives);
+        }
+        let objectives = self
+            .objectives
+            .iter()
+            .enumerate()
+            .map(|(i, objective)| {
+                let impossible = |reason: String| LevelError::Objective { index: i + 1, reason };
+                match *objective {
+                    ObjectiveFile::Score(0) => Err(impossible("目标分数不能为 0".to_owned())),
+                    ObjectiveFile::Score(score) => Ok(Objective::Score(score)),
+                    ObjectiveFile::ClearColor { count: 0, .. } => Err(impossible("消除数量不能为 0".to_owned())),
+                    ObjectiveFile::ClearColor { color, .. } if color == 0 || color > colors => {
+                        Err(impossible(format!("颜色 {color} 不在本关的 1 到 {colors} 之间")))
+                    }
+                    ObjectiveFile::ClearColor { color, .. }
+                        if matches!(spawn, SpawnRule::Weighted(table) if table[color as usize - 1] == 0) =>
+                    {
+                        Err(impossible(format!("颜色 {color} 的补充权重为 0，补充的新宝石不会有这种颜色")))
+                    }
+                    ObjectiveFile::ClearColor { color, count } => Ok(Objective::ClearColor { color, count }),
+                    ObjectiveFile::ClearBlockers if !has_blockers => {
+                        Err(impossible("布局中没有障碍（'#'）".to_owned()))
+                    }
+                    ObjectiveFile::ClearBlockers => Ok(Objective::ClearBlockers),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let stars: [u32; 3] = match self.stars.as_slice() {
+            &[one, two, three] if 0 < one && one < two && two < three => [one, two, three],
+            _ => return Err(LevelError::Stars(self.stars)),
+        };
+
+        Ok(Level {
+            name: self.name,
+            config: GameConfig {
+                width,
+                height,
+                colors,
+                mode,
+                undo_limit: self.undo_limit,
+                spawn,
+            },
+            layout,
+            objectives,
+            stars,
+            seed: self.seed,
+        })
+    }
+}
+
+// 把布局字符串转换成棋盘，随机生成的格子留空
+fn parse_layout(rows: &[String], width: usize, height: usize, colors: u8) -> Result<Board, LevelError> {
+    if rows.len() != height {
+        return Err(LevelError::LayoutRows {
+            expected: height,
+            found: rows.len(),
+        });
+    }
+
+    let mut board = Board::new(width, height);
+    for (i, line) in rows.iter().enumerate() {
+        let cells: Vec<char> = line.chars().collect();
+        if cells.len() != width {
+            return Err(LevelError::LayoutColumns {
+                row: i + 1,
+                expected: width,
+                found: cells.len(),
+            });
+        }
+        for (j, &code) in cells.iter().enumerate() {
+            board[(i, j)] = match code {
+                '.' => Tile::EMPTY,
+                '#' => Tile::BLOCKER,
+                '*' => Tile::COLOR_BOMB,
+                '1'..='8' => {
+                    let color = code as u8 - b'0';
+                    if color > colors {
+                        return Err(LevelError::CellColor {
+                            row: i + 1,
+                            col: j + 1,
+                            color,
+                            colors,
+                        });
+                    }
+                    Tile::gem(color)
+                }
+                _ => return Err(LevelError::UnknownCell { row: i + 1, col: j + 1, code }),
+            };
+        }
+    }
+
+    if let Some(group) = board.find_matches().first() {
+        let (row, col) = group.cells[0];
+        return Err(LevelError::LayoutMatch { row: row + 1, col: col + 1 });
+    }
+    // 随机生成的格子至少要有一种颜色可选，否则开局就会有现成的匹配
+    let no_color = |pos| board[pos].is_empty() && (1..=colors).all(|color| board.would_match(pos, color));
+    if let Some((row, col)) = board.positions().find(|&pos| no_color(pos)) {
+        return Err(LevelError::LayoutNoColor { row: row + 1, col: col + 1 });
+    }
+    Ok(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOALS: &str = "objectives: [Score(100)], stars: [100, 200, 300]";
+
+    // 按给定的宽、高、颜色数和其余字段拼出 RON 关卡并解析
+    fn parse((width, height, colors): (usize, usize, u8), rest: &str) -> Result<Level, LevelError> {
+        let text = format!("(name: \"测试\", width: {width}, height: {height}, colors: {colors}, {rest})");
+        Level::parse(&text, LevelFormat::Ron)
+    }
+
+    // 5x3、4 种颜色、带布局的关卡
+    fn with_layout(rows: &[&str]) -> Result<Level, LevelError> {
+        let rows: Vec<_> = rows.iter().map(|row| format!("{row:?}")).collect();
+        parse((5, 3, 4), &format!("layout: Some([{}]), {GOALS}", rows.join(", ")))
+    }
+
+    #[test]
+    fn parses_every_format() {
+        let rest = format!("moves: Some(20), {GOALS}, layout: None, spawn: Weighted([2, 1])");
+        let level = parse((6, 7, 4), &rest).unwrap();
+        assert_eq!((level.config.width, level.config.height, level.config.colors), (6, 7, 4));
+        assert_eq!(level.config.mode, GameMode::Moves(20));
+        assert_eq!(level.config.spawn, SpawnRule::Weighted([2, 1, 1, 1, 1, 1, 1, 1]));
+        assert_eq!(level.stars, [100, 200, 300]);
+
+        let json = r#"{"name": "测试", "width": 5, "height": 5, "colors": 3, "time": 60.0,
+            "objectives": [{"ClearColor": {"color": 2, "count": 10}}], "stars": [1, 2, 3]}"#;
+        let level = Level::parse(json, LevelFormat::Json).unwrap();
+        assert_eq!(level.config.mode, GameMode::TimeAttack(60.0));
+        assert_eq!(level.objectives, vec![Objective::ClearColor { color: 2, count: 10 }]);
+
+        let toml = "name = \"测试\"\nwidth = 5\nheight = 5\ncolors = 3\n\
+                    objectives = [{ Score = 500 }]\nstars = [1, 2, 3]\n";
+        let level = Level::parse(toml, LevelFormat::Toml).unwrap();
+        assert_eq!(level.config.mode, GameMode::Endless);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(matches!(
+            parse((5, 5, 4), "stars: [1, 2, 3]"),
+            Err(LevelError::Parse { format: LevelFormat::Ron, .. })
+        ));
+        assert!(matches!(
+            parse((5, 5, 4), &format!("lives: 3, {GOALS}")),
+            Err(LevelError::Parse { .. })
+        ));
+        assert!(matches!(
+            Level::parse("{}", LevelFormat::Json),
+            Err(LevelError::Parse { format: LevelFormat::Json, .. })
+        ));
+        assert!(matches!(Level::load("关卡.txt"), Err(LevelError::UnknownFormat(_))));
+        assert!(matches!(Level::load("不存在的关卡.ron"), Err(LevelError::Io(_))));
+    }
+
+    #[test]
+    fn rejects_bad_board_and_limits() {
+        assert!(matches!(parse((2, 5, 4), GOALS), Err(LevelError::BoardSize { width: 2, height: 5 })));
+        assert!(matches!(parse((5, 5, 2), GOALS), Err(LevelError::Colors(2))));
+        assert!(matches!(parse((5, 5, 9), GOALS), Err(LevelError::Colors(9))));
+        assert!(matches!(
+            parse((5, 5, 4), &format!("moves: Some(10), time: Some(30.0), {GOALS}")),
+            Err(LevelError::ConflictingLimits)
+        ));
+        assert!(matches!(parse((5, 5, 4), &format!("moves: Some(0), {GOALS}")), Err(LevelError::ZeroLimit)));
+        assert!(matches!(parse((5, 5, 4), &format!("time: Some(-1.0), {GOALS}")), Err(LevelError::ZeroLimit)));
+    }
+
+    #[test]
+    fn rejects_bad_layouts() {
+        assert!(matches!(
+            with_layout(&[".....", "....."]),
+            Err(LevelError::LayoutRows { expected: 3, found: 2 })
+        ));
+        assert!(matches!(
+            with_layout(&[".....", "....", "....."]),
+            Err(LevelError::LayoutColumns { row: 2, expected: 5, found: 4 })
+        ));
+        assert!(matches!(
+            with_layout(&[".....", "..x..", "....."]),
+            Err(LevelError::UnknownCell { row: 2, col: 3, code: 'x' })
+        ));
+        assert!(matches!(
+            with_layout(&[".....", ".....", "...7."]),
+            Err(LevelError::CellColor { row: 3, col: 4, color: 7, colors: 4 })
+        ));
+        assert!(matches!(
+            with_layout(&[".....", ".222.", "....."]),
+            Err(LevelError::LayoutMatch { row: 2, col: 2 })
+        ));
+        assert!(with_layout(&["#.*..", ".22.1", "....."]).is_ok());
+
+        // (1, 3) 放颜色 1、2 会横着连成三个，放颜色 3 会竖着连成三个
+        let rows = r#"layout: Some(["11.22", "..3..", "..3..", ".....", "....."])"#;
+        assert!(matches!(
+            parse((5, 5, 3), &format!("{rows}, {GOALS}")),
+            Err(LevelError::LayoutNoColor { row: 1, col: 3 })
+        ));
+        assert!(parse((5, 5, 4), &format!("{rows}, {GOALS}")).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_goals() {
+        assert!(matches!(
+            parse((5, 5, 4), "objectives: [], stars: [1, 2, 3]"),
+            Err(LevelError::NoObjectives)
+        ));
+        assert!(matches!(
+            parse((5, 5, 4), "objectives: [Score(100), ClearBlockers], stars: [1, 2, 3]"),
+            Err(LevelError::Objective { index: 2, .. })
+        ));
+        assert!(matches!(
+            parse((5, 5, 4), "objectives: [ClearColor(color: 5, count: 10)], stars: [1, 2, 3]"),
+            Err(LevelError::Objective { index: 1, .. })
+        ));
+        assert!(matches!(
+            parse((5, 5, 3), "moves: Some(10), spawn: Weighted([0, 1, 1]), \
+                              objectives: [ClearColor(color: 1, count: 500)], stars: [1, 2, 3]"),
+            Err(LevelError::Objective { index: 1, .. })
+        ));
+        assert!(parse((5, 5, 3), "spawn: Weighted([0, 1, 1]), \
+                                  objectives: [ClearColor(color: 2, count: 50)], stars: [1, 2, 3]")
+        .is_ok());
+        assert!(matches!(
+            parse((5, 5, 4), "objectives: [Score(100)], stars: [3, 2, 1]"),
+            Err(LevelError::Stars(_))
+        ));
+        assert!(matches!(
+            parse((5, 5, 4), "objectives: [Score(100)], stars: [0, 1, 2]"),
+            Err(LevelError::Stars(_))
+        ));
+        assert!(matches!(
+            parse((5, 5, 4), &format!("spawn: Weighted([1, 1, 1, 1, 1]), {GOALS}")),
+            Err(LevelError::SpawnWeights(_))
+        ));
+        assert!(matches!(
+            parse((5, 5, 4), &format!("spawn: Weighted([0, 0, 0, 0]), {GOALS}")),
+            Err(LevelError::SpawnWeights(_))
+        ));
+        assert!(matches!(
+            parse((5, 5, 4), &format!("spawn: Generous(1.5), {GOALS}")),
+            Err(LevelError::SpawnWeights(_))
+        ));
+    }
+
+    #[test]
+    fn stars_follow_thresholds() {
+        let level = parse((5, 5, 4), GOALS).unwrap();
+        assert_eq!([0, 99, 100, 250, 300, 1000].map(|score| level.stars_for(score)), [0, 0, 1, 2, 3, 3]);
+    }
+}
@@ -0,0 +1,19 @@
+//! 三消游戏核心引擎
+//!
+//! 不依赖 egui 的纯规则层：棋盘、匹配查找、消除计分、下落与补充、交换。
+//! 图形界面（`src/main.rs`）、工具、机器人和测试都通过 [`Game`] 驱动游戏。
+//! 图形界面只在默认的 `gui` 特性下编译，依赖时设置 `default-features = false` 即可只编译引擎。
+
+pub mod board;
+pub mod game;
+pub mod level;
+pub mod matches;
+pub mod spawn;
+pub mod tile;
+
+pub use board::{Board, Fall};
+pub use game::{Game, GameConfig, GameMode, GameStats, Move, Objective, Shift, MAX_COLORS};
+pub use level::{Level, LevelError, LevelFormat};
+pub use matches::{MatchGroup, MatchShape, Orientation};
+pub use spawn::{SpawnPolicy, SpawnRule};
+pub use tile::{Special, Tile};
@@ -1,13 +1,56 @@
- use eframe::egui;
-use rand::Rng;
+use eframe::egui;
+use sanxiao_rust::{Game, GameConfig, GameMode, Level, Move, Objective, Shift, Special, SpawnRule, Tile, MAX_COLORS};
+
+mod easing;
+mod map;
+mod particles;
+mod profile;
+
+use easing::Easing;
+use map::Progress;
+use particles::Particles;
+use profile::{Profile, Settings};
+use serde::{Deserialize, Serialize};
 
-const BOARD_WIDTH: usize = 8;
-const BOARD_HEIGHT: usize = 8;
 const TILE_SIZE: f32 = 40.0;
-const ANIMATION_SPEED: f32 = 300.0; // 像素/秒
+const FALL_GRAVITY: f32 = 2500.0;   // 下落的加速度（像素/秒²），决定下落的速度和时长
+const BOARD_MARGIN: f32 = 10.0;     // 棋盘四周留白
+const UI_EXTRA_HEIGHT: f32 = 415.0; // 棋盘以外的标题、设置和说明所占高度
+const MAX_CLOCK_STEP: f32 = 0.1;    // 限时模式每帧最多走过的秒数（避免窗口恢复焦点时时钟跳变）
+const COMBO_BANNER_TIME: f32 = 1.0; // 连击提示显示的秒数
+const MIN_WINDOW_WIDTH: f32 = 400.0;
+const DRAG_THRESHOLD: f32 = 16.0;   // 拖动超过这个距离（像素）才触发交换
+const HINT_DELAY: f64 = 5.0;        // 玩家多少秒没有操作后自动显示提示
+
+// 各阶段的持续时间（秒）
+const SHAKE_TIME: f32 = 0.25;     // 无效交换弹回后的抖动
+const CLEAR_TIME: f32 = 0.3;      // 被消除的方块缩小淡出
+const SHUFFLE_TIME: f32 = 1.0;    // 洗牌时宝石滑到新位置并显示提示
+const LANDING_TIME: f32 = 0.12;   // 下落的方块落地后弹起再落回
+const SHAKE_AMPLITUDE: f32 = 4.0; // 抖动幅度（像素）
+const LANDING_BOUNCE: f32 = 0.1;  // 落地后弹起的高度（格）
+const SWAP_TIME_RANGE: std::ops::RangeInclusive<f32> = 0.05..=1.0; // 可以设置的交换时长
+const BOARD_SIZE_RANGE: std::ops::RangeInclusive<usize> = 3..=12; // 自由模式可以设置的棋盘宽高
+const MOVES_RANGE: std::ops::RangeInclusive<u32> = 1..=99; // 自由模式可以设置的步数
+const SECONDS_RANGE: std::ops::RangeInclusive<f32> = 10.0..=600.0; // 自由模式可以设置的限时（秒）
+const CHANCE_RANGE: std::ops::RangeInclusive<f64> = 0.0..=1.0; // “容易连锁”送匹配的概率
 
-// 不同颜色的宝石用数字表示：1=红，2=绿，3=蓝，4=黄，5=紫
-type Board = [[u8; BOARD_WIDTH]; BOARD_HEIGHT];
+// 根据棋盘配置计算窗口大小
+fn window_size(config: &GameConfig) -> egui::Vec2 {
+    let board = board_size(config);
+    egui::Vec2::new(
+        (board.x + 2.0 * BOARD_MARGIN).max(MIN_WINDOW_WIDTH),
+        board.y + 2.0 * BOARD_MARGIN + UI_EXTRA_HEIGHT,
+    )
+}
+
+// 棋盘区域大小（不含留白）
+fn board_size(config: &GameConfig) -> egui::Vec2 {
+    egui::Vec2::new(
+        TILE_SIZE * config.width as f32,
+        TILE_SIZE * config.height as f32,
+    )
+}
 
 // 方块动画状态结构体
 // 用于存储单个方块在下落动画过程中的所有状态信息
@@ -16,356 +59,895 @@ struct TileAnimation {
     start_row: f32,      // 动画开始时的起始行位置（浮点数，支持像素级精确定位）
     target_row: f32,     // 动画结束时的目标行位置（方块最终要到达的行）
     current_row: f32,    // 当前动画帧中的行位置（在start_row和target_row之间插值）
-    col: usize,          // 方块所在的列索引（0到BOARD_WIDTH-1）
-    value: u8,           // 方块的值/颜色（1=红，2=绿，3=蓝，4=黄，5=紫）
+    col: usize,          // 方块所在的列索引（0到棋盘宽度-1）
+    tile: Tile,          // 下落的方块（颜色和特殊效果）
     is_active: bool,     // 动画是否还在进行中（true=正在动画，false=已完成或未开始）
+    elapsed: f32,        // 动画已经进行的秒数
+    fall_time: f32,      // 落地的时刻（由下落距离按加速度算出）
+    duration: f32,       // 动画总时长（落地后再弹跳一次）
+    fade_in: bool,       // 是否在原地淡入而不是下落（障碍下方的空位没有方块能落进来）
 }
 
-// 游戏主状态结构体
-// 存储整个游戏的所有状态数据，包括棋盘、分数、用户交互、动画状态等
-struct Game {
-    board: Board,                                    // 8x8的游戏棋盘，存储每个位置的宝石颜色值（0=空，1-5=不同颜色）
-    score: u32,                                      // 当前得分（累计分数）
-    target_score: u32,                               // 目标分数（达到此分数即可获胜）
+impl TileAnimation {
+    fn new(col: usize, start_row: f32, target_row: f32, tile: Tile) -> Self {
+        // 从静止开始匀加速下落：距离 = 加速度 * 时间² / 2
+        let distance = (target_row - start_row) * TILE_SIZE;
+        let fall_time = (2.0 * distance / FALL_GRAVITY).sqrt();
+        TileAnimation {
+            start_row,
+            target_row,
+            current_row: start_row,
+            col,
+            tile,
+            is_active: true,
+            elapsed: 0.0,
+            fall_time,
+            duration: fall_time + LANDING_TIME,
+            fade_in: false,
+        }
+    }
+
+    // 在目标位置原地淡入的新方块
+    fn fade_in(col: usize, row: f32, tile: Tile) -> Self {
+        TileAnimation {
+            duration: CLEAR_TIME,
+            fade_in: true,
+            ..TileAnimation::new(col, row, row, tile)
+        }
+    }
+
+    // 动画开始 `t` 秒后所在的行：从静止开始匀加速下落，落地后轻轻弹起一次再落回（淡入的方块不动）
+    fn row_at(&self, t: f32) -> f32 {
+        if self.fade_in {
+            self.target_row
+        } else if t < self.fall_time {
+            (self.start_row + 0.5 * FALL_GRAVITY * t * t / TILE_SIZE).min(self.target_row)
+        } else {
+            let u = ((t - self.fall_time) / LANDING_TIME).min(1.0);
+            self.target_row - LANDING_BOUNCE * 4.0 * u * (1.0 - u)
+        }
+    }
+
+    // 当前的不透明度（只有淡入的方块会小于 1）
+    fn alpha(&self) -> f32 {
+        if self.fade_in {
+            (self.elapsed / self.duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+
+    // 动画期间这个方块占据的格子（起点和终点），这些格子不按棋盘状态绘制
+    fn covers(&self, (row, col): (usize, usize)) -> bool {
+        self.col == col && (self.start_row == row as f32 || self.target_row == row as f32)
+    }
+}
+
+// 动画参数（可在界面中调整，保存在存档中）
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct AnimationSettings {
+    swap_time: f32, // 两个方块滑动交换所用的秒数
+    easing: Easing, // 交换、消除和洗牌动画的缓动曲线（下落按加速度计算，不使用缓动曲线）
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        AnimationSettings {
+            swap_time: 0.2,
+            easing: Easing::EaseOut,
+        }
+    }
+}
+
+// 交换方块的操作方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum InputMode {
+    #[default]
+    Drag,  // 按住方块向相邻方向拖动
+    Click, // 先后点击两个相邻的方块
+}
+
+// 回合阶段
+// 只有 Idle 阶段接受玩家输入；每个阶段自己保存计时和动画数据，结束时切换到下一个阶段：
+// Idle -> Swapping -> Clearing -> Falling -> (有新匹配则回到 Clearing) -> Refilling
+//      -> (有新匹配则回到 Clearing) -> Idle / Shuffling / GameOver
+// 无效的交换：Idle -> Bouncing -> Idle
+enum Phase {
+    Idle,                                                              // 等待玩家操作
+    Swapping { from: (usize, usize), to: (usize, usize), timer: f32 }, // 两个方块滑动交换，结束后开始消除
+    Bouncing { from: (usize, usize), to: (usize, usize), timer: f32 }, // 无效交换：滑过去再滑回来，然后抖动
+    Clearing { cleared: Vec<((usize, usize), Tile)>, timer: f32 },     // 刚被消除的方块缩小淡出
+    Falling { tiles: Vec<TileAnimation> },                             // 方块下落到空位
+    Refilling { tiles: Vec<TileAnimation> },                           // 新方块从棋盘上方落入空位
+    Shuffling { shifts: Vec<Shift>, timer: f32 },                      // 没有可用移动，宝石滑到洗牌后的位置
+    GameOver,                                                          // 游戏结束
+}
+
+// 当前显示的界面
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Screen {
+    Map,     // 关卡地图
+    Playing, // 正在游戏（关卡或自由模式）
+}
+
+// 界面状态结构体
+// 在规则引擎 Game 之上保存用户交互和动画状态
+struct GameApp {
+    game: Game,                                      // 规则引擎（棋盘、分数、胜负）
+    phase: Phase,                                    // 当前回合阶段
     selected: Option<(usize, usize)>,                // 当前选中的方块坐标（None=未选中，Some((行, 列))=已选中）
-    pending_removal: Vec<(usize, usize)>,            // 待消除的方块坐标列表（用于在消除前高亮显示）
-    animation_timer: f32,                            // 动画计时器（秒），用于控制消除高亮显示的时间
-    game_over: bool,                                 // 游戏是否结束（true=已结束，false=进行中）
-    falling_tiles: Vec<TileAnimation>,               // 正在下落的方块列表（存储所有当前正在播放下落动画的方块）
-    is_animating: bool,                              // 是否正在播放动画（true=有动画进行中，false=无动画，可以接受用户输入）
+    seed_input: String,                              // 种子输入框内容（用于重现指定棋局）
+    config_input: GameConfig,                        // 设置栏中的棋盘尺寸与颜色数（下一局生效）
+    combo_banner: Option<(u32, f32)>,                // 连击提示（连击数, 剩余显示时间）
+    animation: AnimationSettings,                    // 动画参数
+    particles: Particles,                            // 消除时迸出的粒子
+    input_mode: InputMode,                           // 交换方块的操作方式
+    drag_start: Option<((usize, usize), egui::Pos2)>, // 正在拖动的方块及按下时的指针位置
+    cursor: Option<(usize, usize)>,                  // 键盘光标位置（第一次按方向键时出现）
+    paused: bool,                                    // 是否暂停（动画和限时模式的时钟都停止）
+    idle_since: f64,                                 // 玩家最后一次操作的时间（用于自动提示）
+    hint: Option<Move>,                              // 正在提示的移动
+    screen: Screen,                                  // 当前界面
+    levels: Vec<Level>,                              // 关卡包
+    progress: Progress,                              // 关卡包的进度
+    level: Option<usize>,                            // 正在玩的关卡（None=自由模式）
+    level_ids: Vec<&'static str>,                    // 关卡包中每一关的标识（存档按标识记录成绩）
+    profile: Profile,                                // 玩家存档
+    profile_path: Option<std::path::PathBuf>,        // 存档位置（None=不保存）
 }
 
-impl Game {
+impl GameApp {
+    // 读取存档恢复进度和设置，启动时显示关卡地图
     fn new() -> Self {
-        let mut game = Game {
-            board: [[0; BOARD_WIDTH]; BOARD_HEIGHT],
-            score: 0,
-            target_score: 2000,
+        let (profile, profile_path) = profile::open();
+        let settings = profile.settings;
+        let level_ids = map::bundled_level_ids();
+        let records = level_ids.iter().map(|id| profile.levels.get(*id).copied()).collect();
+
+        let mut app = Self::with_game(Game::with_config(settings.free_play, rand::random()));
+        app.animation = settings.animation;
+        app.input_mode = settings.input_mode;
+        app.screen = Screen::Map;
+        app.levels = map::bundled_levels();
+        app.progress = Progress::from_records(records);
+        app.level_ids = level_ids;
+        app.profile = profile;
+        app.profile_path = profile_path;
+        app
+    }
+
+    fn with_game(game: Game) -> Self {
+        GameApp {
+            seed_input: game.seed().to_string(),
+            config_input: *game.config(),
+            game,
+            phase: Phase::Idle,
             selected: None,
-            pending_removal: Vec::new(),
-            animation_timer: 0.0,
-            game_over: false,
-            falling_tiles: Vec::new(),
-            is_animating: false,
+            combo_banner: None,
+            animation: AnimationSettings::default(),
+            particles: Particles::default(),
+            input_mode: InputMode::Drag,
+            drag_start: None,
+            cursor: None,
+            paused: false,
+            idle_since: 0.0,
+            hint: None,
+            screen: Screen::Playing,
+            levels: Vec::new(),
+            progress: Progress::default(),
+            level: None,
+            level_ids: Vec::new(),
+            profile: Profile::default(),
+            profile_path: None,
+        }
+    }
+
+    // 开始一局新游戏，保留设置、关卡包和进度，并按棋盘大小调整窗口
+    fn start(&mut self, ctx: &egui::Context, game: Game, level: Option<usize>) {
+        let config = *game.config();
+        let previous = std::mem::replace(self, GameApp::with_game(game));
+        self.config_input = previous.config_input;
+        self.animation = previous.animation;
+        self.input_mode = previous.input_mode;
+        self.levels = previous.levels;
+        self.progress = previous.progress;
+        self.level_ids = previous.level_ids;
+        self.profile = previous.profile;
+        self.profile_path = previous.profile_path;
+        self.level = level;
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(window_size(&config)));
+    }
+
+    // 按设置栏中的配置开始自由模式的新游戏（不指定种子时随机生成）
+    fn restart(&mut self, ctx: &egui::Context, seed: Option<u64>) {
+        let seed = seed.unwrap_or_else(rand::random);
+        self.start(ctx, Game::with_config(self.config_input, seed), None);
+    }
+
+    // 开始关卡包中的一关（不指定种子时使用关卡的种子或随机生成）
+    fn play_level(&mut self, ctx: &egui::Context, index: usize, seed: Option<u64>) {
+        let level = &self.levels[index];
+        let game = match seed {
+            Some(seed) => level.start_with_seed(seed),
+            None => level.start(),
         };
-        game.fill_board();
-        // 确保初始状态没有三消
-        while game.find_matches().len() > 0 {
-            game.fill_board();
+        self.start(ctx, game, Some(index));
+    }
+
+    // 重新开始当前的关卡或自由模式
+    fn replay(&mut self, ctx: &egui::Context, seed: Option<u64>) {
+        match self.level {
+            Some(index) => self.play_level(ctx, index, seed),
+            None => self.restart(ctx, seed),
         }
-        game
     }
 
-    // 用随机颜色填充游戏板
-    fn fill_board(&mut self) {
-        let mut rng = rand::thread_rng();
-        for i in 0..BOARD_HEIGHT {
-            for j in 0..BOARD_WIDTH {
-                self.board[i][j] = rng.gen_range(1..=5);
+    // 回到关卡地图
+    fn show_map(&mut self, ctx: &egui::Context) {
+        self.screen = Screen::Map;
+        self.paused = false;
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(window_size(&GameConfig::default())));
+    }
+
+    // 一局结束：通过关卡时记录成绩，下一关随之解锁；计入累计统计后保存存档
+    fn finish(&mut self) -> Phase {
+        if let Some(index) = self.level {
+            if self.game.is_won() {
+                let score = self.game.final_score();
+                let stars = self.levels[index].stars_for(score);
+                self.progress.pass(index, score, stars);
             }
         }
+        self.profile.stats.add(&self.game);
+        self.save_profile();
+        Phase::GameOver
     }
 
-    // 获取颜色对应的 RGB
-    fn get_color(cell: u8) -> egui::Color32 {
-        match cell {
-            1 => egui::Color32::from_rgb(255, 80, 80),   // 红
-            2 => egui::Color32::from_rgb(80, 255, 80),   // 绿
-            3 => egui::Color32::from_rgb(80, 80, 255),   // 蓝
-            4 => egui::Color32::from_rgb(255, 255, 80),  // 黄
-            5 => egui::Color32::from_rgb(255, 80, 255),  // 紫
-            _ => egui::Color32::from_rgb(200, 200, 200), // 灰
+    // 当前的界面设置
+    fn settings(&self) -> Settings {
+        Settings {
+            free_play: self.config_input,
+            input_mode: self.input_mode,
+            animation: self.animation,
         }
     }
 
-    // 查找所有可以消除的匹配（三个或更多连续相同）
-    fn find_matches(&self) -> Vec<(usize, usize)> {
-        let mut matches = Vec::new();
-        let mut marked = [[false; BOARD_WIDTH]; BOARD_HEIGHT];
+    // 把关卡成绩和设置写入存档并保存（保存失败只打印错误，不影响游戏）
+    fn save_profile(&mut self) {
+        for (index, id) in self.level_ids.iter().enumerate() {
+            if let Some(record) = self.progress.record(index) {
+                self.profile.levels.insert(id.to_string(), record);
+            }
+        }
+        self.profile.settings = self.settings();
+        if let Some(path) = &self.profile_path {
+            if let Err(err) = self.profile.save(path) {
+                eprintln!("无法保存存档 {}: {}", path.display(), err);
+            }
+        }
+    }
 
-        // 检查水平匹配
-        for i in 0..BOARD_HEIGHT {
-            let mut count = 1;
-            let mut start = 0;
-            for j in 1..BOARD_WIDTH {
-                if self.board[i][j] == self.board[i][j - 1] && self.board[i][j] != 0 {
-                    count += 1;
-                } else {
-                    if count >= 3 {
-                        for k in start..j {
-                            marked[i][k] = true;
+    // 累计统计
+    fn stats_ui(&self, ui: &mut egui::Ui) {
+        let stats = &self.profile.stats;
+        egui::CollapsingHeader::new("累计统计").show(ui, |ui| {
+            ui.label(format!("对局: {}  过关: {}", stats.games_played, stats.games_won));
+            ui.label(format!("单局最高分: {}  最佳连击: {}", stats.best_score, stats.best_combo));
+            ui.label(format!(
+                "总步数: {}  消除宝石: {}  特殊宝石: {}",
+                stats.moves, stats.gems_cleared, stats.specials_created
+            ));
+        });
+    }
+
+    // 关卡地图：每一关的星级和最高分，未解锁的关卡不能进入
+    fn map_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("关卡地图");
+        ui.label(format!("星星: {} / {}", self.progress.total_stars(), 3 * self.levels.len()));
+        ui.add_space(10.0);
+
+        let mut chosen = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("levels").striped(true).spacing([20.0, 8.0]).show(ui, |ui| {
+                for (index, level) in self.levels.iter().enumerate() {
+                    let unlocked = self.progress.is_unlocked(index);
+                    let label = format!("{}. {}", index + 1, level.name);
+                    if ui.add_enabled(unlocked, egui::Button::new(label)).clicked() {
+                        chosen = Some(index);
+                    }
+                    match self.progress.record(index) {
+                        Some(record) => {
+                            ui.label(map::star_text(record.stars));
+                            ui.label(format!("最高分 {}", record.best_score));
+                        }
+                        None if unlocked => {
+                            ui.label(map::star_text(0));
+                            ui.label("未通过");
+                        }
+                        None => {
+                            ui.label("");
+                            ui.label("未解锁");
                         }
                     }
-                    count = 1;
-                    start = j;
+                    ui.end_row();
                 }
+            });
+            ui.add_space(10.0);
+            if ui.button("自由模式").clicked() {
+                self.restart(ui.ctx(), None);
             }
-            if count >= 3 {
-                for k in start..BOARD_WIDTH {
-                    marked[i][k] = true;
-                }
+            ui.add_space(10.0);
+            self.stats_ui(ui);
+        });
+        if let Some(index) = chosen {
+            self.play_level(ui.ctx(), index, None);
+        }
+    }
+
+    // 一局结束后的结果和“下一关 / 重试 / 地图”按钮
+    fn game_over_ui(&mut self, ui: &mut egui::Ui) {
+        let won = self.game.is_won();
+        ui.add_space(10.0);
+        ui.heading(if won {
+            "恭喜过关！"
+        } else if self.game.moves_left() == Some(0) {
+            "步数用完"
+        } else if self.game.time_left() == Some(0.0) {
+            "时间到"
+        } else {
+            "游戏结束"
+        });
+        let bonus = self.game.bonus_points();
+        if bonus > 0 {
+            ui.label(format!("最终得分: {}（含剩余步数/时间奖励 {}）", self.game.final_score(), bonus));
+        } else {
+            ui.label(format!("最终得分: {}", self.game.final_score()));
+        }
+        if let (Some(index), true) = (self.level, won) {
+            let stars = self.levels[index].stars_for(self.game.final_score());
+            ui.heading(map::star_text(stars));
+            if let Some(record) = self.progress.record(index) {
+                ui.label(format!("最高分: {}", record.best_score));
             }
         }
+        let stats = self.game.stats();
+        ui.label(format!("最佳连击: {}", stats.best_combo));
+        ui.label(format!("步数: {}  消除宝石: {}  特殊宝石: {}", stats.moves, stats.gems_cleared, stats.specials_created));
 
-        // 检查垂直匹配
-        for j in 0..BOARD_WIDTH {
-            let mut count = 1;
-            let mut start = 0;
-            for i in 1..BOARD_HEIGHT {
-                if self.board[i][j] == self.board[i - 1][j] && self.board[i][j] != 0 {
-                    count += 1;
-                } else {
-                    if count >= 3 {
-                        for k in start..i {
-                            marked[k][j] = true;
-                        }
-                    }
-                    count = 1;
-                    start = i;
+        ui.horizontal(|ui| {
+            let next = self.level.map(|index| index + 1).filter(|&next| self.progress.is_unlocked(next));
+            if ui.add_enabled(next.is_some(), egui::Button::new("下一关")).clicked() {
+                if let Some(next) = next {
+                    self.play_level(ui.ctx(), next, None);
                 }
             }
-            if count >= 3 {
-                for k in start..BOARD_HEIGHT {
-                    marked[k][j] = true;
-                }
+            if ui.button("重试").clicked() {
+                self.replay(ui.ctx(), None);
+            }
+            if ui.button("地图").clicked() {
+                self.show_map(ui.ctx());
             }
+        });
+    }
+
+    // 设置栏：自由模式的棋盘设置（只在自由模式显示）、种子（重新开始后生效），以及操作方式和动画参数
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        if self.level.is_none() {
+            self.free_play_ui(ui);
         }
+        ui.horizontal(|ui| {
+            ui.label(format!("种子: {}", self.game.seed()));
+            ui.add(egui::TextEdit::singleline(&mut self.seed_input).desired_width(120.0));
+            let seed = self.seed_input.trim().parse::<u64>();
+            if ui.add_enabled(seed.is_ok(), egui::Button::new("使用种子")).clicked() {
+                if let Ok(seed) = seed {
+                    self.replay(ui.ctx(), Some(seed));
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("操作");
+            ui.selectable_value(&mut self.input_mode, InputMode::Drag, "拖动交换");
+            ui.selectable_value(&mut self.input_mode, InputMode::Click, "点击两次交换");
+        });
+        egui::CollapsingHeader::new("动画设置").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("交换时长");
+                ui.add(
+                    egui::DragValue::new(&mut self.animation.swap_time)
+                        .clamp_range(SWAP_TIME_RANGE)
+                        .speed(0.01)
+                        .suffix(" 秒"),
+                );
+            });
+            Self::easing_ui(ui, "缓动曲线", &mut self.animation.easing);
+        });
+    }
 
-        // 收集所有标记的位置
-        for i in 0..BOARD_HEIGHT {
-            for j in 0..BOARD_WIDTH {
-                if marked[i][j] {
-                    matches.push((i, j));
+    // 自由模式的设置：棋盘尺寸、颜色数、模式和补充规则（点击“新游戏”后生效）
+    fn free_play_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("宽");
+            ui.add(egui::DragValue::new(&mut self.config_input.width).clamp_range(BOARD_SIZE_RANGE));
+            ui.label("高");
+            ui.add(egui::DragValue::new(&mut self.config_input.height).clamp_range(BOARD_SIZE_RANGE));
+            ui.label("颜色");
+            ui.add(egui::DragValue::new(&mut self.config_input.colors).clamp_range(3..=MAX_COLORS));
+        });
+        ui.horizontal(|ui| {
+            let mode = &mut self.config_input.mode;
+            ui.label("模式");
+            if ui.selectable_label(matches!(mode, GameMode::Moves(_)), "步数").clicked() {
+                *mode = GameMode::Moves(30);
+            }
+            if ui.selectable_label(matches!(mode, GameMode::TimeAttack(_)), "限时").clicked() {
+                *mode = GameMode::TimeAttack(60.0);
+            }
+            if ui.selectable_label(matches!(mode, GameMode::Endless), "无限").clicked() {
+                *mode = GameMode::Endless;
+            }
+            match mode {
+                GameMode::Moves(limit) => {
+                    ui.add(egui::DragValue::new(limit).clamp_range(MOVES_RANGE).suffix(" 步"));
                 }
+                GameMode::TimeAttack(seconds) => {
+                    ui.add(egui::DragValue::new(seconds).clamp_range(SECONDS_RANGE).suffix(" 秒"));
+                }
+                GameMode::Endless => {}
             }
-        }
+            if ui.button("新游戏").clicked() {
+                self.restart(ui.ctx(), None);
+            }
+        });
+        ui.horizontal(|ui| {
+            let spawn = &mut self.config_input.spawn;
+            ui.label("补充");
+            ui.selectable_value(spawn, SpawnRule::Uniform, "随机");
+            ui.selectable_value(spawn, SpawnRule::NoFreeMatches, "不送三消");
+            if ui.selectable_label(matches!(spawn, SpawnRule::Generous(_)), "容易连锁").clicked() {
+                *spawn = SpawnRule::Generous(0.3);
+            }
+            if let SpawnRule::Generous(chance) = spawn {
+                ui.add(egui::Slider::new(chance, CHANCE_RANGE).show_value(false));
+            }
+        });
+    }
 
-        matches
+    // 一行缓动曲线选项
+    fn easing_ui(ui: &mut egui::Ui, label: &str, easing: &mut Easing) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            for option in Easing::ALL {
+                ui.selectable_value(easing, option, option.name());
+            }
+        });
     }
 
-    // 消除匹配的方块并让上方方块下落
-    fn remove_matches(&mut self) -> bool {
-        let matches = self.find_matches();
-        if matches.is_empty() {
-            return false;
+    // 把棋盘区域内的坐标换算成方块位置（落在方块之间的缝隙里时返回 None）
+    fn tile_at(&self, origin: egui::Pos2, pos: egui::Pos2) -> Option<(usize, usize)> {
+        let offset = pos - origin;
+        if offset.x < 0.0 || offset.y < 0.0 {
+            return None;
         }
+        let (row, col) = ((offset.y / TILE_SIZE) as usize, (offset.x / TILE_SIZE) as usize);
+        let board = self.game.board();
+        let in_tile = offset.x % TILE_SIZE < TILE_SIZE - 2.0 && offset.y % TILE_SIZE < TILE_SIZE - 2.0;
+        (row < board.height() && col < board.width() && in_tile).then_some((row, col))
+    }
 
-        // 计算分数
-        let match_count = matches.len();
-        if match_count >= 5 {
-            self.score += 300;
-        } else if match_count == 4 {
-            self.score += 200;
-        } else {
-            self.score += 100;
+    // 获取颜色对应的 RGB
+    fn get_color(cell: u8) -> egui::Color32 {
+        match cell {
+            1 => egui::Color32::from_rgb(255, 80, 80),   // 红
+            2 => egui::Color32::from_rgb(80, 255, 80),   // 绿
+            3 => egui::Color32::from_rgb(80, 80, 255),   // 蓝
+            4 => egui::Color32::from_rgb(255, 255, 80),  // 黄
+            5 => egui::Color32::from_rgb(255, 80, 255),  // 紫
+            6 => egui::Color32::from_rgb(255, 160, 40),  // 橙
+            7 => egui::Color32::from_rgb(80, 230, 230),  // 青
+            8 => egui::Color32::from_rgb(160, 110, 60),  // 棕
+            _ => egui::Color32::from_rgb(200, 200, 200), // 灰
         }
+    }
 
-        // 记录要消除的方块
-        self.pending_removal = matches.clone();
+    // 绘制一个方块：底色加上特殊宝石的标记
+    fn paint_tile(painter: &egui::Painter, rect: egui::Rect, tile: Tile, color: egui::Color32) {
+        painter.rect_filled(rect, 2.0, color);
 
-        // 消除匹配的方块（设为0）
-        for (i, j) in &matches {
-            self.board[*i][*j] = 0;
+        let stripe = egui::Stroke::new(2.0, egui::Color32::WHITE);
+        let inset = rect.shrink(6.0);
+        match tile.special {
+            Special::None => {}
+            Special::StripedH => {
+                for t in [0.3, 0.5, 0.7] {
+                    let y = egui::lerp(inset.top()..=inset.bottom(), t);
+                    painter.hline(inset.x_range(), y, stripe);
+                }
+            }
+            Special::StripedV => {
+                for t in [0.3, 0.5, 0.7] {
+                    let x = egui::lerp(inset.left()..=inset.right(), t);
+                    painter.vline(x, inset.y_range(), stripe);
+                }
+            }
+            Special::Wrapped => {
+                painter.rect_stroke(inset, 4.0, stripe);
+            }
+            Special::WrappedPrimed => {
+                // 已经炸过一次：外框加中心亮点，提示下一轮还会再炸
+                painter.rect_stroke(inset, 4.0, stripe);
+                painter.circle_filled(rect.center(), 4.0, egui::Color32::WHITE);
+            }
+            Special::ColorBomb => {
+                // 彩色炸弹：深色圆球上环绕一圈各色小点
+                let radius = rect.width() * 0.4;
+                painter.circle_filled(rect.center(), radius, egui::Color32::from_rgb(50, 40, 40));
+                for color in 1..=5u8 {
+                    let angle = std::f32::consts::TAU * color as f32 / 5.0;
+                    let offset = egui::Vec2::angled(angle) * radius * 0.6;
+                    painter.circle_filled(rect.center() + offset, 3.0, Self::get_color(color));
+                }
+            }
+            Special::Blocker => {
+                // 障碍：深灰色石块，画上交叉的裂纹
+                painter.rect_filled(rect, 2.0, egui::Color32::from_rgb(70, 70, 75));
+                let crack = egui::Stroke::new(2.0, egui::Color32::from_rgb(40, 40, 45));
+                painter.line_segment([inset.left_top(), inset.right_bottom()], crack);
+                painter.line_segment([inset.right_top(), inset.left_bottom()], crack);
+            }
         }
+    }
 
-        // 准备并播放下落动画（基于消除后的棋盘状态，不要在动画前更新棋盘）
-        self.prepare_fall_animation();
-
-        true
+    // 过关目标及其进度的说明文字
+    fn objective_text(&self, objective: &Objective) -> String {
+        let (done, total) = self.game.objective_progress(objective);
+        match objective {
+            Objective::Score(_) => format!("达到 {} 分（{}/{}）", total, done, total),
+            Objective::ClearColor { color, .. } => format!("消除颜色 {} 的宝石（{}/{}）", color, done, total),
+            Objective::ClearBlockers => format!("清除所有障碍（{}/{}）", done, total),
+        }
     }
 
-    // 准备下落动画
-    fn prepare_fall_animation(&mut self) {
-        self.falling_tiles.clear();
-        self.is_animating = true;
-        
-        for j in 0..BOARD_WIDTH {
-            // 计算每个方块需要下落的距离
-            let mut fall_distances = vec![0; BOARD_HEIGHT];
-            let mut write_pos = BOARD_HEIGHT - 1;
-            
-            for read_pos in (0..BOARD_HEIGHT).rev() {
-                if self.board[read_pos][j] != 0 {
-                    let fall_distance = write_pos as i32 - read_pos as i32;
-                    if fall_distance > 0 {
-                        fall_distances[read_pos] = fall_distance as usize;
-                    }
-                    if write_pos > 0 {
-                        write_pos -= 1;
-                    }
+    // 推进当前阶段，阶段结束时切换到下一个阶段
+    fn advance(&mut self, delta_time: f32) {
+        self.phase = match std::mem::replace(&mut self.phase, Phase::Idle) {
+            Phase::Idle if self.game.is_over() => self.finish(),
+            Phase::Idle => Phase::Idle,
+            Phase::Swapping { from, to, timer } if timer + delta_time < self.animation.swap_time => Phase::Swapping {
+                from,
+                to,
+                timer: timer + delta_time,
+            },
+            Phase::Swapping { .. } => self.begin_clear(),
+            Phase::Bouncing { from, to, timer } if timer + delta_time < 2.0 * self.animation.swap_time + SHAKE_TIME => {
+                Phase::Bouncing {
+                    from,
+                    to,
+                    timer: timer + delta_time,
                 }
             }
-            
-            // 创建动画对象
-            for i in 0..BOARD_HEIGHT {
-                if fall_distances[i] > 0 && self.board[i][j] != 0 {
-                    self.falling_tiles.push(TileAnimation {
-                        start_row: i as f32,
-                        target_row: (i + fall_distances[i]) as f32,
-                        current_row: i as f32,
-                        col: j,
-                        value: self.board[i][j],
-                        is_active: true,
-                    });
+            Phase::Bouncing { .. } => Phase::Idle,
+            Phase::Clearing { cleared, timer } if timer + delta_time < CLEAR_TIME => Phase::Clearing {
+                cleared,
+                timer: timer + delta_time,
+            },
+            Phase::Clearing { .. } => self.begin_fall(),
+            Phase::Falling { mut tiles } => {
+                if Self::update_fall_animation(&mut tiles, delta_time) {
+                    Phase::Falling { tiles }
+                } else {
+                    // 动画完成后，更新棋盘状态（把方块移到正确位置）
+                    self.game.apply_gravity();
+                    self.after_fall()
+                }
+            }
+            Phase::Refilling { mut tiles } => {
+                if Self::update_fall_animation(&mut tiles, delta_time) {
+                    Phase::Refilling { tiles }
+                } else if self.game.has_pending_clears() {
+                    self.begin_clear()
+                } else {
+                    self.end_turn()
                 }
             }
+            Phase::Shuffling { shifts, timer } if timer + delta_time < SHUFFLE_TIME => Phase::Shuffling {
+                shifts,
+                timer: timer + delta_time,
+            },
+            Phase::Shuffling { .. } => self.end_turn(),
+            Phase::GameOver => Phase::GameOver,
+        };
+    }
+
+    // 消除一轮匹配，进入高亮阶段；没有可消除的方块时结束本回合
+    fn begin_clear(&mut self) -> Phase {
+        let before = self.game.board().clone();
+        let cleared = self.game.remove_matches();
+        if cleared.is_empty() {
+            return self.end_turn();
         }
-        
-        // 如果没有创建任何动画对象，说明不需要动画
-        if self.falling_tiles.is_empty() {
-            self.is_animating = false;
+
+        // 连锁消除时显示连击提示
+        if self.game.combo() >= 2 {
+            self.combo_banner = Some((self.game.combo(), COMBO_BANNER_TIME));
         }
+
+        // 记下被消除的方块原来的样子，用于消除动画，并在每个位置迸出粒子
+        let cleared: Vec<_> = cleared.into_iter().map(|pos| (pos, before[pos])).collect();
+        for &((row, col), tile) in &cleared {
+            let center = egui::Vec2::new(col as f32 + 0.5, row as f32 + 0.5) * TILE_SIZE;
+            self.particles.burst(center, Self::get_color(tile.color));
+        }
+        Phase::Clearing { cleared, timer: 0.0 }
     }
 
-    // 带动画的方块下落
-    fn drop_tiles_with_animation(&mut self) {
-        // 在动画过程中，更新实际棋盘
-        for j in 0..BOARD_WIDTH {
-            let mut write_pos = BOARD_HEIGHT - 1;
-            for read_pos in (0..BOARD_HEIGHT).rev() {
-                if self.board[read_pos][j] != 0 {
-                    if read_pos != write_pos {
-                        self.board[write_pos][j] = self.board[read_pos][j];
-                        self.board[read_pos][j] = 0;
-                    }
-                    if write_pos > 0 {
-                        write_pos -= 1;
-                    }
-                }
-            }
+    // 准备下落动画（基于消除后的棋盘状态，动画结束前不更新棋盘）
+    fn begin_fall(&mut self) -> Phase {
+        let tiles: Vec<_> = self
+            .game
+            .falls()
+            .into_iter()
+            .map(|fall| TileAnimation::new(fall.col, fall.from_row as f32, fall.to_row as f32, fall.tile))
+            .collect();
+
+        // 如果没有创建任何动画对象，说明不需要动画
+        if tiles.is_empty() {
+            self.after_fall()
+        } else {
+            Phase::Falling { tiles }
         }
     }
-    
-    // 更新下落动画
-    fn update_fall_animation(&mut self, delta_time: f32) -> bool {
-        if !self.is_animating {
-            return false;
+
+    // 下落结束：有新的匹配先继续消除，否则填充顶部空白位置的新方块
+    fn after_fall(&mut self) -> Phase {
+        if self.game.has_pending_clears() {
+            self.begin_clear()
+        } else {
+            self.begin_refill()
         }
-        if self.falling_tiles.is_empty() {
-            // 如果没有动画对象但标记为正在动画，清除标记
-            self.is_animating = false;
-            return false;
+    }
+
+    // 填充空位，新方块从棋盘上方落入（同一列最上面的障碍以上补充 n 个时，从顶部以上 n 行处开始下落）；
+    // 障碍下方的空位没有方块能落进来，新方块在原地淡入
+    fn begin_refill(&mut self) -> Phase {
+        let filled = self.game.fill_empty();
+        let board = self.game.board();
+        let sheltered = |(row, col): (usize, usize)| (0..row).any(|i| board[(i, col)].is_blocker());
+        let tiles = filled
+            .iter()
+            .map(|&(row, col)| {
+                if sheltered((row, col)) {
+                    return TileAnimation::fade_in(col, row as f32, board[(row, col)]);
+                }
+                let count = filled.iter().filter(|&&pos| pos.1 == col && !sheltered(pos)).count();
+                TileAnimation::new(col, row as f32 - count as f32, row as f32, board[(row, col)])
+            })
+            .collect();
+        Phase::Refilling { tiles }
+    }
+
+    // 棋盘稳定后结束本回合：判断游戏是否结束，没有可用移动时洗牌
+    fn end_turn(&mut self) -> Phase {
+        if self.game.is_over() {
+            self.finish()
+        } else if self.game.has_moves() {
+            Phase::Idle
+        } else if let Some(shifts) = self.game.reshuffle() {
+            Phase::Shuffling { shifts, timer: 0.0 }
+        } else {
+            self.finish()
         }
-        
+    }
+
+    // 更新下落动画，返回是否还有方块在下落
+    fn update_fall_animation(tiles: &mut [TileAnimation], delta_time: f32) -> bool {
         let mut all_finished = true;
-        let pixel_delta = ANIMATION_SPEED * delta_time / TILE_SIZE;
-        
-        for tile in &mut self.falling_tiles {
-            if tile.is_active && tile.current_row < tile.target_row {
-                tile.current_row += pixel_delta;
-                if tile.current_row >= tile.target_row {
+
+        for tile in tiles.iter_mut() {
+            if tile.is_active {
+                tile.elapsed += delta_time;
+                if tile.elapsed >= tile.duration {
                     tile.current_row = tile.target_row;
                     tile.is_active = false;
                 } else {
+                    tile.current_row = tile.row_at(tile.elapsed);
                     all_finished = false;
                 }
             }
         }
-        
-        if all_finished {
-            // 动画完成后，更新棋盘状态（把方块移到正确位置）
-            self.drop_tiles_with_animation();
-            
-            // 检查是否有新的匹配需要消除
-            let has_matches = !self.find_matches().is_empty();
-            
-            if has_matches {
-                // 有新的匹配，清除动画状态，重置计时器以便立即触发下一轮消除
-                self.falling_tiles.clear();
-                self.is_animating = false;
-                self.animation_timer = 0.6; // 设置为大于0.5，让update函数立即触发消除
-            } else {
-                // 没有新匹配，填充顶部空白位置的新方块
-                self.fill_empty();
-                // 让新方块下落（更新棋盘状态）
-                self.drop_tiles_with_animation();
-                // 检查新方块是否需要下落动画
-                self.prepare_fall_animation();
-                // 如果 prepare_fall_animation 没有创建任何动画（因为新方块已经在正确位置），则清除动画状态
-                if self.falling_tiles.is_empty() {
-                    self.is_animating = false;
-                }
-            }
-        }
-        
+
         !all_finished
     }
 
-    // 填充空白位置
-    fn fill_empty(&mut self) {
-        let mut rng = rand::thread_rng();
-        for i in 0..BOARD_HEIGHT {
-            for j in 0..BOARD_WIDTH {
-                if self.board[i][j] == 0 {
-                    self.board[i][j] = rng.gen_range(1..=5);
-                }
+    // 交换或洗牌动画中某个格子里的方块相对于格子的偏移（像素）
+    fn tile_offset(&self, pos: (usize, usize)) -> egui::Vec2 {
+        let easing = self.animation.easing;
+        let step = |a: (usize, usize), b: (usize, usize)| {
+            egui::Vec2::new(b.1 as f32 - a.1 as f32, b.0 as f32 - a.0 as f32) * TILE_SIZE
+        };
+        let (from, to, progress, shake) = match &self.phase {
+            // 引擎已经洗好牌，宝石从原来的位置滑到新位置
+            Phase::Shuffling { shifts, timer } => {
+                let progress = 1.0 - easing.apply(timer / SHUFFLE_TIME);
+                return shifts
+                    .iter()
+                    .find(|shift| shift.to == pos)
+                    .map_or(egui::Vec2::ZERO, |shift| step(shift.to, shift.from) * progress);
+            }
+            // 引擎已经交换了两个方块，从原来的格子滑到新格子
+            &Phase::Swapping { from, to, timer } => {
+                (to, from, 1.0 - easing.apply(timer / self.animation.swap_time), 0.0)
+            }
+            // 引擎没有交换：先滑向对方再滑回来，最后抖动
+            &Phase::Bouncing { from, to, timer } => {
+                let swap_time = self.animation.swap_time;
+                let progress = if timer < swap_time {
+                    easing.apply(timer / swap_time)
+                } else {
+                    1.0 - easing.apply(timer / swap_time - 1.0)
+                };
+                let shake_timer = timer - 2.0 * swap_time;
+                let shake = if shake_timer > 0.0 {
+                    let decay = 1.0 - shake_timer / SHAKE_TIME;
+                    (shake_timer / SHAKE_TIME * 4.0 * std::f32::consts::TAU).sin() * SHAKE_AMPLITUDE * decay
+                } else {
+                    0.0
+                };
+                (from, to, progress, shake)
             }
+            _ => return egui::Vec2::ZERO,
+        };
+
+        let progress = progress.clamp(0.0, 1.0);
+        if pos == from {
+            step(from, to) * progress + egui::Vec2::new(shake, 0.0)
+        } else if pos == to {
+            step(to, from) * progress + egui::Vec2::new(shake, 0.0)
+        } else {
+            egui::Vec2::ZERO
         }
     }
 
-    // 交换两个相邻的方块
-    fn swap(&mut self, row1: usize, col1: usize, row2: usize, col2: usize) -> bool {
-        let row_diff = (row1 as i32 - row2 as i32).abs();
-        let col_diff = (col1 as i32 - col2 as i32).abs();
-        
-        if row_diff + col_diff != 1 {
-            return false;
+    // 尝试交换两个相邻的方块，没有匹配时引擎会自动交换回来，界面播放弹回动画
+    fn swap_tiles(&mut self, from: (usize, usize), to: (usize, usize)) {
+        self.phase = if self.game.try_swap(from, to) {
+            Phase::Swapping { from, to, timer: 0.0 }
+        } else {
+            Phase::Bouncing { from, to, timer: 0.0 }
+        };
+        self.selected = None;
+    }
+
+    // 处理拖动：按住一个方块拖过阈值后，与拖动方向（取横竖中较大的分量）上相邻的方块交换
+    fn handle_drag(&mut self, origin: egui::Pos2, response: &egui::Response) {
+        if response.drag_started() {
+            let press = response.ctx.input(|i| i.pointer.press_origin());
+            self.drag_start = press.and_then(|press| Some((self.tile_at(origin, press)?, press)));
+            self.selected = self.drag_start.map(|(pos, _)| pos);
+        }
+        if response.drag_stopped() {
+            self.drag_start = None;
+            self.selected = None;
+            return;
         }
 
-        let temp = self.board[row1][col1];
-        self.board[row1][col1] = self.board[row2][col2];
-        self.board[row2][col2] = temp;
+        let (Some(((row, col), press)), Some(pointer)) = (self.drag_start, response.interact_pointer_pos()) else {
+            return;
+        };
+        let delta = pointer - press;
+        if delta.length() < DRAG_THRESHOLD {
+            return;
+        }
+        let target = if delta.x.abs() > delta.y.abs() {
+            (Some(row), col.checked_add_signed(delta.x.signum() as isize))
+        } else {
+            (row.checked_add_signed(delta.y.signum() as isize), Some(col))
+        };
 
-        true
+        // 每次按下只触发一次交换，拖出棋盘边缘时取消
+        self.drag_start = None;
+        self.selected = None;
+        let board = self.game.board();
+        if let (Some(r), Some(c)) = target {
+            if r < board.height() && c < board.width() {
+                self.swap_tiles((row, col), (r, c));
+            }
+        }
     }
 
-    // 检查是否有可用的移动
-    fn has_moves(&self) -> bool {
-        for i in 0..BOARD_HEIGHT {
-            for j in 0..BOARD_WIDTH {
-                if j < BOARD_WIDTH - 1 {
-                    let mut test_board = self.board;
-                    test_board[i][j] = self.board[i][j + 1];
-                    test_board[i][j + 1] = self.board[i][j];
-                    
-                    let temp_game = Game { 
-                        board: test_board, 
-                        score: 0, 
-                        target_score: 0, 
-                        selected: None, 
-                        pending_removal: Vec::new(), 
-                        animation_timer: 0.0, 
-                        game_over: false,
-                        falling_tiles: Vec::new(),
-                        is_animating: false,
-                    };
-                    if temp_game.find_matches().len() > 0 {
-                        return true;
-                    }
-                }
-                if i < BOARD_HEIGHT - 1 {
-                    let mut test_board = self.board;
-                    test_board[i][j] = self.board[i + 1][j];
-                    test_board[i + 1][j] = self.board[i][j];
-                    
-                    let temp_game = Game { 
-                        board: test_board, 
-                        score: 0, 
-                        target_score: 0, 
-                        selected: None, 
-                        pending_removal: Vec::new(), 
-                        animation_timer: 0.0, 
-                        game_over: false,
-                        falling_tiles: Vec::new(),
-                        is_animating: false,
-                    };
-                    if temp_game.find_matches().len() > 0 {
-                        return true;
-                    }
-                }
+    // 处理键盘：方向键/WASD 移动光标，空格/回车选中光标处的方块，选中后按方向键与该方向的方块交换；
+    // H 提示，Z 撤销，Y 重做，P/Esc 暂停，R 重新开始（输入框有焦点时不处理）
+    fn handle_keys(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let pressed = |keys: &[egui::Key]| ctx.input(|i| keys.iter().any(|&key| i.key_pressed(key)));
+
+        if pressed(&[egui::Key::R]) {
+            self.replay(ctx, None);
+            return;
+        }
+        if pressed(&[egui::Key::P, egui::Key::Escape]) && !matches!(self.phase, Phase::GameOver) {
+            self.paused = !self.paused;
+        }
+        if self.paused || !matches!(self.phase, Phase::Idle) {
+            return;
+        }
+        if pressed(&[egui::Key::H]) {
+            self.show_hint();
+        }
+        if pressed(&[egui::Key::Z]) {
+            self.undo();
+        }
+        if pressed(&[egui::Key::Y]) {
+            self.redo();
+        }
+
+        let direction = if pressed(&[egui::Key::ArrowUp, egui::Key::W]) {
+            Some((-1, 0))
+        } else if pressed(&[egui::Key::ArrowDown, egui::Key::S]) {
+            Some((1, 0))
+        } else if pressed(&[egui::Key::ArrowLeft, egui::Key::A]) {
+            Some((0, -1))
+        } else if pressed(&[egui::Key::ArrowRight, egui::Key::D]) {
+            Some((0, 1))
+        } else {
+            None
+        };
+
+        let board = self.game.board();
+        let (width, height) = (board.width(), board.height());
+        let Some(cursor) = self.cursor else {
+            // 光标还没出现时，第一次按键只显示光标
+            if direction.is_some() || pressed(&[egui::Key::Space, egui::Key::Enter]) {
+                self.cursor = Some(self.selected.unwrap_or((0, 0)));
             }
+            return;
+        };
+
+        if let Some((dr, dc)) = direction {
+            let target = (cursor.0.checked_add_signed(dr), cursor.1.checked_add_signed(dc));
+            let (Some(row), Some(col)) = target else {
+                return;
+            };
+            if row >= height || col >= width {
+                return;
+            }
+            if self.selected == Some(cursor) {
+                self.swap_tiles(cursor, (row, col));
+            } else {
+                self.cursor = Some((row, col));
+            }
+        } else if pressed(&[egui::Key::Space, egui::Key::Enter]) {
+            self.selected = if self.selected == Some(cursor) { None } else { Some(cursor) };
+        }
+    }
+
+    // 撤销上一步（只在等待玩家操作时可用）
+    fn undo(&mut self) {
+        if matches!(self.phase, Phase::Idle) && self.game.undo() {
+            self.selected = None;
+            self.hint = None;
+        }
+    }
+
+    // 重做被撤销的一步
+    fn redo(&mut self) {
+        if matches!(self.phase, Phase::Idle) && self.game.redo() {
+            self.selected = None;
+            self.hint = None;
         }
-        false
+    }
+
+    // 提示当前得分最高的一步（分数相同时取靠前的）
+    fn show_hint(&mut self) {
+        self.hint = self
+            .game
+            .find_moves()
+            .into_iter()
+            .rev()
+            .max_by_key(Move::points);
     }
 
     // 处理方块点击
@@ -374,21 +956,8 @@ impl Game {
             if sel_row == row && sel_col == col {
                 // 取消选择
                 self.selected = None;
-            } else if (sel_row == row && (sel_col as i32 - col as i32).abs() == 1)
-                || (sel_col == col && (sel_row as i32 - row as i32).abs() == 1)
-            {
-                // 尝试交换
-                if self.swap(sel_row, sel_col, row, col) {
-                    let matches = self.find_matches();
-                    if matches.is_empty() {
-                        // 没有匹配，交换回来
-                        self.swap(sel_row, sel_col, row, col);
-                    } else {
-                        // 有匹配，消除
-                        self.remove_matches();
-                    }
-                }
-                self.selected = None;
+            } else if sel_row.abs_diff(row) + sel_col.abs_diff(col) == 1 {
+                self.swap_tiles((sel_row, sel_col), (row, col));
             } else {
                 // 选择新方块
                 self.selected = Some((row, col));
@@ -402,209 +971,223 @@ impl Game {
     // 更新游戏状态
     fn update(&mut self, ctx: &egui::Context) {
         let delta_time = ctx.input(|i| i.unstable_dt);
-        self.animation_timer += delta_time;
 
-        // 更新下落动画
-        if self.update_fall_animation(delta_time) {
-            // 动画进行中，不进行其他操作
-            return;
+        // 玩家有操作、暂停或不在等待操作时重新计算空闲时间，空闲够久后自动提示
+        let now = ctx.input(|i| i.time);
+        let input = ctx.input(|i| i.pointer.any_pressed() || i.events.iter().any(|e| matches!(e, egui::Event::Key { .. })));
+        if !matches!(self.phase, Phase::Idle) {
+            self.hint = None;
         }
-
-        // 检查是否有空格需要处理（如果不在动画中且有空格）
-        if !self.is_animating && self.pending_removal.is_empty() {
-            let mut has_empty = false;
-            for i in 0..BOARD_HEIGHT {
-                for j in 0..BOARD_WIDTH {
-                    if self.board[i][j] == 0 {
-                        has_empty = true;
-                        break;
-                    }
-                }
-                if has_empty {
-                    break;
-                }
-            }
-            
-            if has_empty {
-                // 有空格，让方块下落并准备动画
-                self.drop_tiles_with_animation();
-                self.fill_empty();
-                self.drop_tiles_with_animation();
-                self.prepare_fall_animation();
-                // 如果仍然没有动画，说明不需要动画，但应该继续
-                if self.falling_tiles.is_empty() {
-                    self.is_animating = false;
-                }
-                return; // 等待下一帧继续
+        if input || self.paused || !matches!(self.phase, Phase::Idle) {
+            self.idle_since = now;
+        } else if self.hint.is_none() {
+            let idle = now - self.idle_since;
+            if idle >= HINT_DELAY {
+                self.show_hint();
+            } else {
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(HINT_DELAY - idle));
             }
         }
-
-        // 清除待消除标记
-        if self.animation_timer > 0.3 && !self.pending_removal.is_empty() {
-            self.pending_removal.clear();
-            self.animation_timer = 0.0;
+        if self.hint.is_some() {
+            ctx.request_repaint();
         }
 
-        // 自动消除（仅在动画结束后）
-        if self.pending_removal.is_empty() && !self.is_animating && self.animation_timer > 0.5 {
-            while self.remove_matches() {
-                self.animation_timer = 0.0;
-                break;
-            }
+        if self.paused {
+            return;
         }
 
-        // 检查是否有可用移动
-        if self.pending_removal.is_empty() && !self.has_moves() {
-            if !self.game_over {
-                // 没有可用移动时，先尝试重新洗牌
-                let attempts = 5;
-                let mut shuffled = false;
-                for _ in 0..attempts {
-                    self.fill_board();
-                    if self.has_moves() {
-                        shuffled = true;
-                        break;
-                    }
-                }
-                // 如果重新洗牌后还是没有可用移动，游戏结束
-                if !shuffled {
-                    self.game_over = true;
-                }
+        // 连击提示逐渐消失
+        if let Some((_, time)) = &mut self.combo_banner {
+            *time -= delta_time;
+            if *time <= 0.0 {
+                self.combo_banner = None;
+            } else {
+                ctx.request_repaint();
             }
         }
-        
-        // 检查是否达到目标分数
-        if self.score >= self.target_score && !self.game_over {
-            self.game_over = true;
-        }
 
-        // 清除待消除标记
-        if self.animation_timer > 0.3 && !self.pending_removal.is_empty() {
-            self.pending_removal.clear();
-            self.animation_timer = 0.0;
+        // 限时模式的时钟只在窗口有焦点时走动
+        if self.game.time_left().is_some() && !self.game.is_over() && ctx.input(|i| i.focused) {
+            self.game.tick(delta_time.min(MAX_CLOCK_STEP));
+            ctx.request_repaint();
         }
 
-        // 如果正在播放动画，持续请求重绘
-        if self.is_animating {
+        self.advance(delta_time);
+        self.particles.update(delta_time);
+
+        // 动画阶段或还有粒子时持续请求重绘
+        if !matches!(self.phase, Phase::Idle | Phase::GameOver) || !self.particles.is_empty() {
             ctx.request_repaint();
         }
     }
 }
 
-impl eframe::App for Game {
+impl eframe::App for GameApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // 设置有变化时保存；拖动数值时每帧都在变，等松开鼠标后再保存
+        if self.settings() != self.profile.settings && !ctx.input(|i| i.pointer.any_down()) {
+            self.save_profile();
+        }
+
+        if self.screen == Screen::Map {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.vertical_centered(|ui| self.map_ui(ui));
+            });
+            return;
+        }
+        self.handle_keys(ctx);
         self.update(ctx);
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
-                ui.heading("三消游戏");
-                ui.label(format!("分数: {} / {}", self.score, self.target_score));
-                
-                // 检查游戏结束
-                if self.game_over || self.score >= self.target_score {
-                    ui.add_space(10.0);
-                    ui.heading(if self.score >= self.target_score {
-                        "恭喜过关！"
+                match self.level {
+                    Some(index) => ui.heading(format!("第 {} 关：{}", index + 1, self.levels[index].name)),
+                    None => ui.heading("三消游戏（自由模式）"),
+                };
+                match self.game.target_score() {
+                    Some(target) => ui.label(format!("分数: {} / {}", self.game.score(), target)),
+                    None => ui.label(format!("分数: {}", self.game.score())),
+                };
+                if let Some(moves) = self.game.moves_left() {
+                    ui.label(format!("剩余步数: {}", moves));
+                }
+                if let Some(time) = self.game.time_left() {
+                    if (ctx.input(|i| i.focused) && !self.paused) || self.game.is_over() {
+                        ui.label(format!("剩余时间: {:.1} 秒", time));
                     } else {
-                        "游戏结束"
-                    });
-                    if ui.button("重新开始").clicked() {
-                        *self = Game::new();
+                        ui.label(format!("剩余时间: {:.1} 秒（已暂停）", time));
                     }
+                }
+                self.settings_ui(ui);
+                
+                // 检查游戏结束
+                if matches!(self.phase, Phase::GameOver) {
+                    self.game_over_ui(ui);
                     return;
                 }
                 
-                ui.add_space(20.0);
+                ui.add_space(10.0);
+                let idle = matches!(self.phase, Phase::Idle) && !self.paused;
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(idle, egui::Button::new("提示 (H)")).clicked() {
+                        self.show_hint();
+                    }
+                    let undo_label = match self.game.undos_left() {
+                        Some(left) => format!("撤销 (Z) 剩余 {}", left),
+                        None => "撤销 (Z)".to_owned(),
+                    };
+                    if ui.add_enabled(idle && self.game.can_undo(), egui::Button::new(undo_label)).clicked() {
+                        self.undo();
+                    }
+                    if ui.add_enabled(idle && self.game.can_redo(), egui::Button::new("重做 (Y)")).clicked() {
+                        self.redo();
+                    }
+                    if ui.button("地图").clicked() {
+                        self.show_map(ui.ctx());
+                    }
+                });
+                ui.add_space(10.0);
 
                 // 绘制游戏板
-                let board_size = TILE_SIZE * BOARD_WIDTH as f32;
+                let board_size = board_size(self.game.config());
                 let (response, painter) = ui.allocate_painter(
-                    egui::Vec2::new(board_size + 20.0, board_size + 20.0),
-                    egui::Sense::click(),
+                    board_size + egui::Vec2::splat(2.0 * BOARD_MARGIN),
+                    egui::Sense::click_and_drag(),
                 );
 
                 let rect = response.rect;
-                let start_x = rect.left() + 10.0;
-                let start_y = rect.top() + 10.0;
-
-                // 绘制网格和方块
-                for i in 0..BOARD_HEIGHT {
-                    for j in 0..BOARD_WIDTH {
-                        let x = start_x + j as f32 * TILE_SIZE;
-                        let y = start_y + i as f32 * TILE_SIZE;
-                        
-                        let tile_rect = egui::Rect::from_min_size(
-                            egui::Pos2::new(x, y),
-                            egui::Vec2::new(TILE_SIZE - 2.0, TILE_SIZE - 2.0),
-                        );
-
-                        // 检查是否被点击（动画期间不能点击）
-                        if !self.is_animating && response.clicked() {
+                let start_x = rect.left() + BOARD_MARGIN;
+                let start_y = rect.top() + BOARD_MARGIN;
+
+                // 检查是否点击或拖动了方块（只在等待玩家操作时接受输入）
+                let origin = egui::Pos2::new(start_x, start_y);
+                let pulse = (0.5 + 0.5 * (ui.input(|i| i.time) * 6.0).sin()) as f32;
+                if matches!(self.phase, Phase::Idle) && !self.paused {
+                    match self.input_mode {
+                        InputMode::Drag => self.handle_drag(origin, &response),
+                        InputMode::Click if response.clicked() => {
                             if let Some(click_pos) = response.interact_pointer_pos() {
-                                if tile_rect.contains(click_pos) {
+                                if let Some((i, j)) = self.tile_at(origin, click_pos) {
                                     self.handle_click(i, j);
                                 }
                             }
                         }
+                        InputMode::Click => {}
                     }
                 }
 
+                let falling: &[TileAnimation] = match &self.phase {
+                    Phase::Falling { tiles } | Phase::Refilling { tiles } => tiles,
+                    _ => &[],
+                };
+                let (cleared, clear_progress): (&[_], f32) = match &self.phase {
+                    Phase::Clearing { cleared, timer } => (cleared, timer / CLEAR_TIME),
+                    _ => (&[], 0.0),
+                };
+
                 // 首先绘制固定位置的方块（非动画中的）
-                for i in 0..BOARD_HEIGHT {
-                    for j in 0..BOARD_WIDTH {
-                        // 检查这个位置是否有正在动画的方块
-                        let has_falling = self.falling_tiles.iter()
-                            .any(|t| t.col == j && (t.start_row as usize) == i);
-                        
-                        if has_falling {
-                            continue; // 这个位置的方块正在动画，稍后绘制
-                        }
-                        
-                        let x = start_x + j as f32 * TILE_SIZE;
-                        let y = start_y + i as f32 * TILE_SIZE;
-                        
-                        let tile_rect = egui::Rect::from_min_size(
-                            egui::Pos2::new(x, y),
-                            egui::Vec2::new(TILE_SIZE - 2.0, TILE_SIZE - 2.0),
-                        );
-
-                        // 绘制方块背景
-                        let mut color = Self::get_color(self.board[i][j]);
-                        
-                        // 如果被选中，改变颜色
-                        if let Some((sel_row, sel_col)) = self.selected {
-                            if sel_row == i && sel_col == j {
-                                color = color.gamma_multiply(1.5);
-                            }
-                        }
+                let board = self.game.board();
+                for (i, j) in board.positions() {
+                    // 检查这个位置是否有正在动画的方块
+                    let has_falling = falling.iter().any(|t| t.covers((i, j)));
+                    
+                    if has_falling {
+                        continue; // 这个位置的方块正在动画，稍后绘制
+                    }
+                    
+                    let x = start_x + j as f32 * TILE_SIZE;
+                    let y = start_y + i as f32 * TILE_SIZE;
+                    
+                    let tile_rect = egui::Rect::from_min_size(
+                        egui::Pos2::new(x, y),
+                        egui::Vec2::new(TILE_SIZE - 2.0, TILE_SIZE - 2.0),
+                    )
+                    .translate(self.tile_offset((i, j)));
 
-                        // 如果待消除，变暗
-                        if self.pending_removal.contains(&(i, j)) {
-                            color = color.gamma_multiply(0.3);
+                    // 绘制方块背景
+                    let tile = board[(i, j)];
+                    let mut color = Self::get_color(tile.color);
+                    
+                    // 如果被选中，改变颜色
+                    if let Some((sel_row, sel_col)) = self.selected {
+                        if sel_row == i && sel_col == j {
+                            color = color.gamma_multiply(1.5);
                         }
+                    }
 
-                        painter.rect_filled(tile_rect, 2.0, color);
-                        
-                        // 绘制边框
-                        let border_color = if let Some((sel_row, sel_col)) = self.selected {
-                            if sel_row == i && sel_col == j {
-                                egui::Color32::WHITE
-                            } else {
-                                egui::Color32::from_rgb(150, 150, 150)
-                            }
+                    Self::paint_tile(&painter, tile_rect, tile, color);
+                    
+                    // 绘制边框
+                    let border_color = if let Some((sel_row, sel_col)) = self.selected {
+                        if sel_row == i && sel_col == j {
+                            egui::Color32::WHITE
                         } else {
                             egui::Color32::from_rgb(150, 150, 150)
-                        };
-                        painter.rect_stroke(tile_rect, 2.0, (1.0, border_color));
+                        }
+                    } else {
+                        egui::Color32::from_rgb(150, 150, 150)
+                    };
+                    painter.rect_stroke(tile_rect, 2.0, (1.0, border_color));
+
+                    // 提示的两个方块闪烁外框
+                    if self.hint.as_ref().is_some_and(|hint| hint.from == (i, j) || hint.to == (i, j)) {
+                        let stroke = (2.0, egui::Color32::WHITE.gamma_multiply(pulse));
+                        painter.rect_stroke(tile_rect.expand(1.0 + 2.0 * pulse), 3.0, stroke);
+                    }
+
+                    // 刚被消除的方块在原位置上缩小并淡出
+                    if let Some(&(_, removed)) = cleared.iter().find(|(pos, _)| *pos == (i, j)) {
+                        let fade = 1.0 - self.animation.easing.apply(clear_progress);
+                        let removed_rect = egui::Rect::from_center_size(tile_rect.center(), tile_rect.size() * fade);
+                        Self::paint_tile(&painter, removed_rect, removed, Self::get_color(removed.color).gamma_multiply(fade));
                     }
                 }
                 
-                // 绘制正在下落的方块（覆盖在上方）
-                for tile in &self.falling_tiles {
-                    if !tile.is_active {
-                        continue;
-                    }
-                    
+                // 绘制正在下落的方块（覆盖在上方，已经落到位的方块停在目标位置）
+                // 从棋盘上方落入的新方块只在棋盘区域内可见
+                let board_rect = egui::Rect::from_min_size(egui::Pos2::new(start_x, start_y), board_size);
+                let clipped = painter.with_clip_rect(board_rect.intersect(painter.clip_rect()));
+                for tile in falling {
                     let x = start_x + tile.col as f32 * TILE_SIZE;
                     let y = start_y + tile.current_row * TILE_SIZE;
                     
@@ -613,14 +1196,65 @@ impl eframe::App for Game {
                         egui::Vec2::new(TILE_SIZE - 2.0, TILE_SIZE - 2.0),
                     );
                     
-                    let color = Self::get_color(tile.value);
-                    painter.rect_filled(tile_rect, 2.0, color);
-                    painter.rect_stroke(tile_rect, 2.0, (1.0, egui::Color32::from_rgb(150, 150, 150)));
+                    let alpha = tile.alpha();
+                    Self::paint_tile(&clipped, tile_rect, tile.tile, Self::get_color(tile.tile.color).gamma_multiply(alpha));
+                    clipped.rect_stroke(tile_rect, 2.0, (1.0, egui::Color32::from_rgb(150, 150, 150).gamma_multiply(alpha)));
+                }
+
+                self.particles.paint(&painter, egui::Pos2::new(start_x, start_y));
+
+                // 键盘光标
+                if let Some((i, j)) = self.cursor {
+                    let cursor_rect = egui::Rect::from_min_size(
+                        egui::Pos2::new(start_x + j as f32 * TILE_SIZE, start_y + i as f32 * TILE_SIZE),
+                        egui::Vec2::splat(TILE_SIZE - 2.0),
+                    );
+                    painter.rect_stroke(cursor_rect.expand(1.0), 3.0, (3.0, egui::Color32::from_rgb(255, 215, 0)));
+                }
+
+                // 棋盘中央的提示文字可能比窄棋盘更宽，用不裁剪到棋盘区域的 painter 绘制
+                let overlay = ui.painter();
+
+                // 连击提示（覆盖在棋盘中央，逐渐淡出）
+                if let Some((combo, time)) = self.combo_banner {
+                    let alpha = (time / COMBO_BANNER_TIME).clamp(0.0, 1.0);
+                    overlay.text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        format!("连击 x{}!", combo),
+                        egui::FontId::proportional(32.0),
+                        egui::Color32::from_rgb(255, 215, 0).gamma_multiply(alpha),
+                    );
+                }
+
+                if self.paused {
+                    painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+                    overlay.text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        "已暂停（按 P 继续）",
+                        egui::FontId::proportional(24.0),
+                        egui::Color32::WHITE,
+                    );
+                } else if matches!(self.phase, Phase::Shuffling { .. }) {
+                    overlay.text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        "没有可用移动——洗牌中",
+                        egui::FontId::proportional(24.0),
+                        egui::Color32::WHITE,
+                    );
                 }
 
                 ui.add_space(20.0);
-                ui.label("操作说明：点击相邻的两个方块来交换");
-                ui.label(format!("目标：达到 {} 分", self.target_score));
+                ui.label(match self.input_mode {
+                    InputMode::Drag => "操作说明：按住方块拖向相邻的方块来交换",
+                    InputMode::Click => "操作说明：点击相邻的两个方块来交换",
+                });
+                ui.label("键盘：方向键/WASD 移动光标，空格/回车选中后按方向键交换；H 提示，Z/Y 撤销/重做，P 暂停，R 重新开始");
+                for objective in self.game.objectives() {
+                    ui.label(format!("目标：{}", self.objective_text(objective)));
+                }
             });
         });
     }
@@ -629,7 +1263,7 @@ impl eframe::App for Game {
 fn main() -> eframe::Result<()> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([400.0, 550.0])
+            .with_inner_size(window_size(&GameConfig::default()))
             .with_title("三消游戏"),
         ..Default::default()
     };
@@ -639,6 +1273,7 @@ fn main() -> eframe::Result<()> {
         options,
         Box::new(|cc| {
             // 配置中文字体
+            #[cfg_attr(not(target_os = "windows"), allow(unused_mut))]
             let mut fonts = egui::FontDefinitions::default();
             
             // 尝试使用系统字体
@@ -672,7 +1307,42 @@ fn main() -> eframe::Result<()> {
             
             cc.egui_ctx.set_fonts(fonts);
             
-            Box::new(Game::new())
+            Box::new(GameApp::new())
         }),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fall_time_follows_distance() {
+        for (start, target) in [(0.0, 1.0), (-3.0, 2.0), (1.0, 8.0)] {
+            let tile = TileAnimation::new(0, start, target, Tile::gem(1));
+            let distance = (target - start) * TILE_SIZE;
+            assert!((tile.fall_time - (2.0 * distance / FALL_GRAVITY).sqrt()).abs() < 1e-6);
+            assert_eq!(tile.duration, tile.fall_time + LANDING_TIME);
+        }
+        // 越落越快：距离变为四倍，时间只变为两倍
+        let short = TileAnimation::new(0, 0.0, 1.0, Tile::gem(1)).fall_time;
+        let long = TileAnimation::new(0, 0.0, 4.0, Tile::gem(1)).fall_time;
+        assert!((long - 2.0 * short).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fall_lands_on_target_and_bounces_back() {
+        let tile = TileAnimation::new(0, -2.0, 3.0, Tile::gem(1));
+        assert_eq!(tile.row_at(0.0), -2.0);
+        assert!((tile.row_at(tile.fall_time) - 3.0).abs() < 1e-4);
+        // 落地后弹起，不会穿过目标行
+        let bounce = tile.row_at(tile.fall_time + LANDING_TIME / 2.0);
+        assert!((3.0 - LANDING_BOUNCE..3.0).contains(&bounce));
+        assert_eq!(tile.row_at(tile.fall_time + LANDING_TIME), 3.0);
+        assert_eq!(tile.row_at(tile.duration + 1.0), 3.0);
+
+        let fade = TileAnimation::fade_in(0, 4.0, Tile::gem(1));
+        assert_eq!(fade.row_at(0.0), 4.0);
+        assert_eq!(fade.row_at(fade.duration / 2.0), 4.0);
+    }
+}
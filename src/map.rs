@@ -0,0 +1,117 @@
+// 关卡地图：内置关卡包，以及每一关的最高分、星级和解锁状态
+use sanxiao_rust::{Level, LevelFormat};
+use serde::{Deserialize, Serialize};
+
+// 内置关卡包（按顺序解锁），编译时嵌入 levels 目录下的关卡文件
+const BUNDLED_LEVELS: [(&str, &str); 8] = [
+    ("01_first_steps.ron", include_str!("../levels/01_first_steps.ron")),
+    ("02_five_colors.ron", include_str!("../levels/02_five_colors.ron")),
+    ("03_red_harvest.ron", include_str!("../levels/03_red_harvest.ron")),
+    ("04_stone_wall.ron", include_str!("../levels/04_stone_wall.ron")),
+    ("05_against_the_clock.ron", include_str!("../levels/05_against_the_clock.ron")),
+    ("06_narrow_alley.ron", include_str!("../levels/06_narrow_alley.ron")),
+    ("07_six_colors.ron", include_str!("../levels/07_six_colors.ron")),
+    ("08_fortress.ron", include_str!("../levels/08_fortress.ron")),
+];
+
+// 解析内置关卡包（关卡文件随程序发布，出错说明文件本身有问题）
+pub fn bundled_levels() -> Vec<Level> {
+    BUNDLED_LEVELS
+        .iter()
+        .map(|(file, text)| {
+            Level::parse(text, LevelFormat::Ron).unwrap_or_else(|err| panic!("内置关卡 {} 无效: {}", file, err))
+        })
+        .collect()
+}
+
+// 内置关卡的标识（关卡文件名去掉扩展名），存档按标识记录成绩，调整关卡顺序不影响已有进度
+pub fn bundled_level_ids() -> Vec<&'static str> {
+    BUNDLED_LEVELS
+        .iter()
+        .map(|(file, _)| file.trim_end_matches(".ron"))
+        .collect()
+}
+
+// 一关的最好成绩
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelRecord {
+    pub best_score: u32, // 过关时的最高得分（含过关奖励）
+    pub stars: u32,      // 得到过的最多星数（0 到 3）
+}
+
+// 关卡包的进度：第一关总是解锁，之后的关卡在前一关通过后解锁
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+    records: Vec<Option<LevelRecord>>, // 每一关的成绩（None=还没有通过）
+}
+
+impl Progress {
+    // 从存档中的成绩恢复进度（按关卡顺序）
+    pub fn from_records(records: Vec<Option<LevelRecord>>) -> Self {
+        Progress { records }
+    }
+
+    pub fn record(&self, index: usize) -> Option<LevelRecord> {
+        self.records.get(index).copied().flatten()
+    }
+
+    pub fn is_passed(&self, index: usize) -> bool {
+        self.record(index).is_some()
+    }
+
+    pub fn is_unlocked(&self, index: usize) -> bool {
+        index < self.records.len() && (index == 0 || self.is_passed(index - 1))
+    }
+
+    // 记录一次过关，保留最高分和最多星数
+    pub fn pass(&mut self, index: usize, score: u32, stars: u32) {
+        let Some(slot) = self.records.get_mut(index) else {
+            return;
+        };
+        let old = slot.unwrap_or_default();
+        *slot = Some(LevelRecord {
+            best_score: old.best_score.max(score),
+            stars: old.stars.max(stars),
+        });
+    }
+
+    // 所有关卡得到的星数之和
+    pub fn total_stars(&self) -> u32 {
+        self.records.iter().flatten().map(|record| record.stars).sum()
+    }
+}
+
+// 用实心和空心星星显示星级，例如 2 星显示为 "★★☆"
+pub fn star_text(stars: u32) -> String {
+    (0..3).map(|i| if i < stars { '★' } else { '☆' }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_levels_are_valid() {
+        let levels = bundled_levels();
+        assert_eq!(levels.len(), bundled_level_ids().len());
+        for level in &levels {
+            let game = level.start_with_seed(0);
+            assert!(game.find_matches().is_empty() && game.has_moves(), "{}", level.name);
+            assert_eq!(level.stars_for(level.stars[0]), 1);
+        }
+    }
+
+    #[test]
+    fn passing_unlocks_the_next_level_and_keeps_the_best() {
+        let mut progress = Progress::from_records(vec![None; 3]);
+        assert!(progress.is_unlocked(0) && !progress.is_unlocked(1));
+        progress.pass(0, 5000, 2);
+        progress.pass(0, 3000, 3);
+        assert_eq!(progress.record(0), Some(LevelRecord { best_score: 5000, stars: 3 }));
+        assert!(progress.is_unlocked(1) && !progress.is_unlocked(2));
+        assert!(!progress.is_unlocked(3));
+        assert_eq!(progress.total_stars(), 3);
+        assert_eq!(star_text(2), "★★☆");
+    }
+}
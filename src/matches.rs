@@ -0,0 +1,122 @@
+/// 一条直线上三个或更多连续同色的宝石
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Run {
+    pub cells: Vec<(usize, usize)>, // 按从左到右或从上到下排列的位置
+    pub horizontal: bool,           // 是否为横向
+    pub color: u8,                  // 宝石颜色
+}
+
+/// 匹配的方向（L/T 形取较长的一条边）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Horizontal, // 横向
+    Vertical,   // 纵向
+}
+
+/// 匹配的形状
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchShape {
+    Three, // 直线三连
+    Four,  // 直线四连
+    Five,  // 直线五连及以上
+    L,     // 横竖两条线在端点相交
+    T,     // 横竖两条线相交，交点在至少一条线的中间（含十字形）
+}
+
+impl MatchShape {
+    /// 这种形状的匹配的基础得分
+    pub fn points(&self) -> u32 {
+        match self {
+            MatchShape::Three => 100,
+            MatchShape::Four => 200,
+            MatchShape::L | MatchShape::T => 300,
+            MatchShape::Five => 500,
+        }
+    }
+}
+
+/// 一组相连的同色匹配：单条直线，或共用一个格子的横竖直线（L/T 形）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchGroup {
+    pub cells: Vec<(usize, usize)>,    // 组内所有位置，按行优先顺序排列
+    pub orientation: Orientation,      // 匹配方向
+    pub shape: MatchShape,             // 匹配形状
+    pub color: u8,                     // 宝石颜色
+    pub pivot: Option<(usize, usize)>, // L/T 形横竖两条线的交点
+}
+
+impl MatchGroup {
+    // 由若干条共用格子的直线构成一组匹配
+    fn from_runs(runs: &[&Run]) -> Self {
+        let mut cells: Vec<_> = runs.iter().flat_map(|run| run.cells.iter().copied()).collect();
+        cells.sort_unstable();
+        cells.dedup();
+
+        let longest = runs
+            .iter()
+            .max_by_key(|run| (run.cells.len(), run.horizontal))
+            .expect("匹配组至少包含一条直线");
+        let orientation = if longest.horizontal {
+            Orientation::Horizontal
+        } else {
+            Orientation::Vertical
+        };
+
+        // 找到横竖两条线的交点
+        let crossing = runs.iter().filter(|run| run.horizontal).find_map(|h| {
+            runs.iter().filter(|run| !run.horizontal).find_map(|v| {
+                h.cells
+                    .iter()
+                    .find(|pos| v.cells.contains(pos))
+                    .map(|&pos| (pos, is_end(h, pos) && is_end(v, pos)))
+            })
+        });
+
+        let shape = match (longest.cells.len(), crossing) {
+            (5.., _) => MatchShape::Five,
+            (_, Some((_, true))) => MatchShape::L,
+            (_, Some((_, false))) => MatchShape::T,
+            (4, None) => MatchShape::Four,
+            _ => MatchShape::Three,
+        };
+
+        MatchGroup {
+            cells,
+            orientation,
+            shape,
+            color: longest.color,
+            pivot: crossing.map(|(pos, _)| pos),
+        }
+    }
+}
+
+// 位置是否在直线的某一端
+fn is_end(run: &Run, pos: (usize, usize)) -> bool {
+    run.cells.first() == Some(&pos) || run.cells.last() == Some(&pos)
+}
+
+/// 把共用格子的直线合并成匹配组
+pub(crate) fn group_runs(runs: &[Run]) -> Vec<MatchGroup> {
+    // 每条直线所属的组号，共用格子的直线合并到同一组
+    let mut group_of: Vec<usize> = (0..runs.len()).collect();
+    for i in 0..runs.len() {
+        for j in i + 1..runs.len() {
+            if runs[i].cells.iter().any(|pos| runs[j].cells.contains(pos)) {
+                let (from, to) = (group_of[j], group_of[i]);
+                for group in group_of.iter_mut().filter(|group| **group == from) {
+                    *group = to;
+                }
+            }
+        }
+    }
+
+    let mut ids = group_of.clone();
+    ids.sort_unstable();
+    ids.dedup();
+    ids.into_iter()
+        .map(|id| {
+            let members: Vec<_> = runs.iter().zip(&group_of).filter(|(_, &g)| g == id).map(|(run, _)| run).collect();
+            MatchGroup::from_runs(&members)
+        })
+        .collect()
+}
@@ -0,0 +1,68 @@
+// 消除宝石时迸出的粒子特效（只用于界面绘制，不影响游戏规则）
+use eframe::egui;
+use rand::Rng;
+
+const MAX_PARTICLES: usize = 300;     // 同时存在的粒子上限，超过时不再产生新粒子
+const PARTICLES_PER_BURST: usize = 8; // 每个被消除的宝石迸出的粒子数
+const PARTICLE_LIFETIME: f32 = 0.6;   // 粒子存在的秒数
+const PARTICLE_SPEED: f32 = 120.0;    // 粒子初速度的上限（像素/秒）
+const PARTICLE_GRAVITY: f32 = 300.0;  // 粒子受到的重力加速度（像素/秒²）
+const PARTICLE_RADIUS: f32 = 3.0;     // 粒子初始半径（像素）
+
+// 单个粒子，位置以棋盘左上角为原点
+struct Particle {
+    pos: egui::Vec2,      // 当前位置
+    velocity: egui::Vec2, // 速度（像素/秒）
+    color: egui::Color32, // 颜色
+    age: f32,             // 已经存在的秒数
+}
+
+// 粒子系统：保存所有存活的粒子
+#[derive(Default)]
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    // 在某个位置向四周迸出一团粒子（达到上限后多余的粒子直接丢弃）
+    pub fn burst(&mut self, center: egui::Vec2, color: egui::Color32) {
+        let mut rng = rand::thread_rng();
+        let count = PARTICLES_PER_BURST.min(MAX_PARTICLES - self.particles.len());
+        for _ in 0..count {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let speed = rng.gen_range(0.3..1.0) * PARTICLE_SPEED;
+            self.particles.push(Particle {
+                pos: center,
+                velocity: egui::Vec2::angled(angle) * speed,
+                color,
+                age: 0.0,
+            });
+        }
+    }
+
+    // 移动粒子并移除寿命已到的粒子
+    pub fn update(&mut self, delta_time: f32) {
+        for particle in &mut self.particles {
+            particle.velocity.y += PARTICLE_GRAVITY * delta_time;
+            particle.pos += particle.velocity * delta_time;
+            particle.age += delta_time;
+        }
+        self.particles.retain(|particle| particle.age < PARTICLE_LIFETIME);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    // 以 origin 为棋盘左上角绘制所有粒子，随寿命缩小并淡出
+    pub fn paint(&self, painter: &egui::Painter, origin: egui::Pos2) {
+        for particle in &self.particles {
+            let life = 1.0 - particle.age / PARTICLE_LIFETIME;
+            painter.circle_filled(
+                origin + particle.pos,
+                PARTICLE_RADIUS * life,
+                particle.color.gamma_multiply(life),
+            );
+        }
+    }
+}
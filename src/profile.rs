@@ -0,0 +1,319 @@
+// 玩家存档：关卡成绩、设置和累计统计，保存在系统的数据目录中（Linux 下为 $XDG_DATA_HOME/sanxiao_rust）
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use sanxiao_rust::{Game, GameConfig, GameMode, SpawnRule};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::map::LevelRecord;
+use crate::{AnimationSettings, InputMode, BOARD_SIZE_RANGE, CHANCE_RANGE, MOVES_RANGE, SECONDS_RANGE, SWAP_TIME_RANGE};
+
+// 存档格式的升级步骤：MIGRATIONS[i] 把第 i+1 版的存档升级为第 i+2 版。
+// 修改存档格式时在末尾追加一步，当前版本号随之增加，旧存档读取时依次执行缺少的步骤
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+// 当前的存档格式版本
+const PROFILE_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+const PROFILE_FILE: &str = "profile.json";
+
+// 存档文件的默认位置（找不到系统数据目录时为 None，此时不保存）
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("sanxiao_rust").join(PROFILE_FILE))
+}
+
+// 读取存档时的错误
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(std::io::Error),       // 读取文件失败
+    Parse(serde_json::Error), // 文件内容损坏
+    Newer(u32),               // 存档来自更新版本的游戏
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Io(err) => write!(f, "无法读取存档: {}", err),
+            ProfileError::Parse(err) => write!(f, "存档已损坏: {}", err),
+            ProfileError::Newer(version) => {
+                write!(f, "存档版本 {} 比当前游戏支持的版本 {} 更新", version, PROFILE_VERSION)
+            }
+        }
+    }
+}
+
+// 界面设置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub free_play: GameConfig,        // 自由模式的棋盘设置
+    pub input_mode: InputMode,        // 交换方块的操作方式
+    pub animation: AnimationSettings, // 动画参数
+}
+
+// 所有对局的累计统计
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub games_played: u32,     // 结束的对局数
+    pub games_won: u32,        // 过关的对局数
+    pub best_score: u32,       // 单局最高分（含过关奖励）
+    pub best_combo: u32,       // 最多的连锁消除轮数
+    pub moves: u64,            // 总步数
+    pub gems_cleared: u64,     // 总共消除的宝石数
+    pub specials_created: u64, // 总共生成的特殊宝石数
+}
+
+impl LifetimeStats {
+    // 计入一局结束的游戏
+    pub fn add(&mut self, game: &Game) {
+        let stats = game.stats();
+        self.games_played += 1;
+        self.games_won += u32::from(game.is_won());
+        self.best_score = self.best_score.max(game.final_score());
+        self.best_combo = self.best_combo.max(stats.best_combo);
+        self.moves += u64::from(stats.moves);
+        self.gems_cleared += u64::from(stats.gems_cleared);
+        self.specials_created += u64::from(stats.specials_created);
+    }
+}
+
+// 存档内容（缺少的字段取默认值，未知的字段忽略）
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub version: u32,                          // 存档格式版本
+    pub levels: BTreeMap<String, LevelRecord>, // 通过的关卡的成绩（按关卡标识），通过的关卡的下一关随之解锁
+    pub settings: Settings,                    // 界面设置
+    pub stats: LifetimeStats,                  // 累计统计
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            version: PROFILE_VERSION,
+            levels: BTreeMap::new(),
+            settings: Settings::default(),
+            stats: LifetimeStats::default(),
+        }
+    }
+}
+
+impl Profile {
+    // 读取存档，旧版本的存档先升级到当前版本；文件不存在时返回默认存档
+    pub fn load(path: &Path) -> Result<Profile, ProfileError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Profile::default()),
+            Err(err) => return Err(ProfileError::Io(err)),
+        };
+        let mut value: Value = serde_json::from_str(&text).map_err(ProfileError::Parse)?;
+        upgrade(&mut value, MIGRATIONS)?;
+
+        let mut profile: Profile = serde_json::from_value(value).map_err(ProfileError::Parse)?;
+        repair_free_play(&mut profile.settings.free_play);
+        // 交换时长不为正时动画进度会算出 NaN
+        let swap_time = &mut profile.settings.animation.swap_time;
+        *swap_time = swap_time.clamp(*SWAP_TIME_RANGE.start(), *SWAP_TIME_RANGE.end());
+        Ok(profile)
+    }
+
+    // 保存存档：先完整写入同目录下的临时文件，再改名覆盖旧存档，中途退出也不会留下写了一半的存档
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(self)?;
+        let temp = path.with_extension("json.tmp");
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    }
+}
+
+// 依次执行存档缺少的升级步骤（migrations 的含义同 MIGRATIONS），并把版本号改为升级后的版本
+fn upgrade(value: &mut Value, migrations: &[fn(&mut Value)]) -> Result<(), ProfileError> {
+    let latest = migrations.len() as u32 + 1;
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(1).max(1);
+    if version > u64::from(latest) {
+        return Err(ProfileError::Newer(version.min(u64::from(u32::MAX)) as u32));
+    }
+    for migrate in &migrations[version as usize - 1..] {
+        migrate(value);
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_owned(), latest.into());
+    }
+    Ok(())
+}
+
+// 手动修改过的存档可能带有无法开局或界面上调不出来的自由模式设置：
+// 无法开局时换回默认设置，其余的数值限制在设置界面允许的范围内
+fn repair_free_play(config: &mut GameConfig) {
+    if !config.is_valid() {
+        *config = GameConfig::default();
+    }
+    config.width = config.width.clamp(*BOARD_SIZE_RANGE.start(), *BOARD_SIZE_RANGE.end());
+    config.height = config.height.clamp(*BOARD_SIZE_RANGE.start(), *BOARD_SIZE_RANGE.end());
+    match &mut config.mode {
+        GameMode::Moves(limit) => *limit = (*limit).clamp(*MOVES_RANGE.start(), *MOVES_RANGE.end()),
+        GameMode::TimeAttack(seconds) => *seconds = seconds.clamp(*SECONDS_RANGE.start(), *SECONDS_RANGE.end()),
+        GameMode::Endless => {}
+    }
+    if let SpawnRule::Generous(chance) = &mut config.spawn {
+        *chance = chance.clamp(*CHANCE_RANGE.start(), *CHANCE_RANGE.end());
+    }
+}
+
+// 打开默认位置的存档，返回存档和之后保存的位置（None=不保存）。
+// 存档损坏时改名为 profile.json.bad 留作备份后从头开始；无法读取或来自更新版本的游戏时不保存，以免覆盖原存档
+pub fn open() -> (Profile, Option<PathBuf>) {
+    let Some(path) = default_path() else {
+        return (Profile::default(), None);
+    };
+    match Profile::load(&path) {
+        Ok(profile) => (profile, Some(path)),
+        Err(err @ ProfileError::Parse(_)) => {
+            eprintln!("{}", err);
+            match std::fs::rename(&path, path.with_extension("json.bad")) {
+                Ok(()) => (Profile::default(), Some(path)),
+                Err(err) => {
+                    eprintln!("无法备份损坏的存档，本次不会保存进度: {}", err);
+                    (Profile::default(), None)
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("{}，本次不会保存进度", err);
+            (Profile::default(), None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试用的临时存档，结束时删除
+    struct TempProfile(PathBuf);
+
+    impl TempProfile {
+        fn new(name: &str, text: Option<&str>) -> Self {
+            let path = std::env::temp_dir().join(format!("sanxiao_rust_{}_{}.json", std::process::id(), name));
+            if let Some(text) = text {
+                std::fs::write(&path, text).unwrap();
+            }
+            TempProfile(path)
+        }
+    }
+
+    impl Drop for TempProfile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn missing_file_gives_default() {
+        let file = TempProfile::new("missing", None);
+        assert_eq!(Profile::load(&file.0).unwrap(), Profile::default());
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = TempProfile::new("round_trip", None);
+        let mut profile = Profile::default();
+        profile.levels.insert("01_first_steps".to_owned(), LevelRecord { best_score: 4200, stars: 2 });
+        profile.stats.games_played = 3;
+        profile.save(&file.0).unwrap();
+        assert_eq!(Profile::load(&file.0).unwrap(), profile);
+    }
+
+    #[test]
+    fn unversioned_profile_is_upgraded() {
+        let text = r#"{"levels": {"01_first_steps": {"best_score": 1500}}, "removed_field": true}"#;
+        let file = TempProfile::new("unversioned", Some(text));
+        let profile = Profile::load(&file.0).unwrap();
+        assert_eq!(profile.version, PROFILE_VERSION);
+        assert_eq!(profile.levels["01_first_steps"], LevelRecord { best_score: 1500, stars: 0 });
+        assert_eq!(profile.settings, Settings::default());
+    }
+
+    #[test]
+    fn newer_profile_is_rejected() {
+        let text = format!(r#"{{"version": {}}}"#, PROFILE_VERSION + 1);
+        let file = TempProfile::new("newer", Some(&text));
+        assert!(matches!(Profile::load(&file.0), Err(ProfileError::Newer(version)) if version == PROFILE_VERSION + 1));
+    }
+
+    #[test]
+    fn corrupt_profile_is_a_parse_error() {
+        let file = TempProfile::new("corrupt", Some("{\"version\": 1, \"levels\": ["));
+        assert!(matches!(Profile::load(&file.0), Err(ProfileError::Parse(_))));
+    }
+
+    #[test]
+    fn invalid_settings_are_repaired() {
+        let text = r#"{"version": 1, "settings": {"free_play": {"width": 1}, "animation": {"swap_time": -1.0}}}"#;
+        let file = TempProfile::new("invalid_settings", Some(text));
+        let settings = Profile::load(&file.0).unwrap().settings;
+        assert_eq!(settings.free_play, GameConfig::default());
+        assert_eq!(settings.animation.swap_time, *SWAP_TIME_RANGE.start());
+    }
+
+    #[test]
+    fn free_play_is_clamped_to_the_settings_ranges() {
+        let text = r#"{"version": 1, "settings": {"free_play": {"width": 40, "height": 3, "colors": 4,
+            "mode": {"Moves": 500}, "spawn": {"Generous": 7.5}}}}"#;
+        let file = TempProfile::new("clamped_free_play", Some(text));
+        let config = Profile::load(&file.0).unwrap().settings.free_play;
+        assert_eq!((config.width, config.height, config.colors), (*BOARD_SIZE_RANGE.end(), 3, 4));
+        assert_eq!(config.mode, GameMode::Moves(*MOVES_RANGE.end()));
+        assert_eq!(config.spawn, SpawnRule::Generous(*CHANCE_RANGE.end()));
+
+        let mut config = GameConfig { mode: GameMode::Moves(0), spawn: SpawnRule::Generous(-1.0), ..Default::default() };
+        repair_free_play(&mut config);
+        assert_eq!(config.mode, GameMode::Moves(*MOVES_RANGE.start()));
+        assert_eq!(config.spawn, SpawnRule::Generous(*CHANCE_RANGE.start()));
+        for (seconds, clamped) in [(1.0, SECONDS_RANGE.start()), (1e9, SECONDS_RANGE.end()), (90.0, &90.0)] {
+            let mut config = GameConfig { mode: GameMode::TimeAttack(seconds), ..Default::default() };
+            repair_free_play(&mut config);
+            assert_eq!(config.mode, GameMode::TimeAttack(*clamped));
+        }
+    }
+
+    // 测试用的升级步骤：第 1 版的关卡成绩只有 score 字段，第 2 版改名为 best_score
+    fn rename_score(value: &mut Value) {
+        let Some(levels) = value.get_mut("levels").and_then(Value::as_object_mut) else {
+            return;
+        };
+        for record in levels.values_mut().filter_map(Value::as_object_mut) {
+            if let Some(score) = record.remove("score") {
+                record.insert("best_score".to_owned(), score);
+            }
+        }
+    }
+
+    #[test]
+    fn migrations_run_from_the_saved_version() {
+        let migrations: &[fn(&mut Value)] = &[rename_score];
+
+        let mut value: Value = serde_json::from_str(r#"{"levels": {"01_first_steps": {"score": 900}}}"#).unwrap();
+        upgrade(&mut value, migrations).unwrap();
+        let profile: Profile = serde_json::from_value(value).unwrap();
+        assert_eq!(profile.version, 2);
+        assert_eq!(profile.levels["01_first_steps"], LevelRecord { best_score: 900, stars: 0 });
+
+        // 已经是第 2 版的存档不再执行第 1 步
+        let mut value: Value = serde_json::from_str(r#"{"version": 2, "levels": {"a": {"score": 5}}}"#).unwrap();
+        upgrade(&mut value, migrations).unwrap();
+        assert_eq!(value["levels"]["a"]["score"], 5);
+
+        let mut value: Value = serde_json::from_str(r#"{"version": 3}"#).unwrap();
+        assert!(matches!(upgrade(&mut value, migrations), Err(ProfileError::Newer(3))));
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::board::Board;
+use crate::game::MAX_COLORS;
+
+/// 补充新宝石时挑选颜色的策略
+///
+/// 补充按行优先顺序进行，挑选某个空位的颜色时，它下方的宝石和同一轮中已经补充的宝石都已经在棋盘上。
+pub trait SpawnPolicy: Send + Sync {
+    /// 为棋盘上 `pos` 处的空位挑选一种颜色，必须在 1..=colors 之间
+    ///
+    /// 0 表示空格，超出颜色数的宝石不在本局的调色板里；[`Game`](crate::Game) 会把超出范围的结果限制到这个区间。
+    fn pick(&self, board: &Board, pos: (usize, usize), colors: u8, rng: &mut StdRng) -> u8;
+}
+
+/// 均匀随机挑选颜色
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Uniform;
+
+impl SpawnPolicy for Uniform {
+    fn pick(&self, _board: &Board, _pos: (usize, usize), colors: u8, rng: &mut StdRng) -> u8 {
+        rng.gen_range(1..=colors)
+    }
+}
+
+/// 按权重挑选颜色：`weights[i]` 是颜色 i+1 的权重，缺少的颜色权重为 1
+#[derive(Clone, Debug)]
+pub struct Weighted {
+    pub weights: Vec<u32>,
+}
+
+impl SpawnPolicy for Weighted {
+    fn pick(&self, board: &Board, pos: (usize, usize), colors: u8, rng: &mut StdRng) -> u8 {
+        let weights = (0..colors as usize).map(|i| self.weights.get(i).copied().unwrap_or(1));
+        match WeightedIndex::new(weights) {
+            Ok(index) => index.sample(rng) as u8 + 1,
+            // 权重全为 0 时退回均匀随机
+            Err(_) => Uniform.pick(board, pos, colors, rng),
+        }
+    }
+}
+
+/// 尽量不补充会直接连成三个的颜色，避免白送的连锁消除
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoFreeMatches;
+
+impl SpawnPolicy for NoFreeMatches {
+    fn pick(&self, board: &Board, pos: (usize, usize), colors: u8, rng: &mut StdRng) -> u8 {
+        let safe: Vec<u8> = (1..=colors).filter(|&color| !board.would_match(pos, color)).collect();
+        match safe.choose(rng) {
+            Some(&color) => color,
+            None => Uniform.pick(board, pos, colors, rng),
+        }
+    }
+}
+
+/// 以一定概率补充能直接连成三个的颜色，让连锁消除更容易出现
+#[derive(Clone, Copy, Debug)]
+pub struct Generous {
+    pub chance: f64, // 能凑成匹配时选择匹配颜色的概率（0 到 1）
+}
+
+impl SpawnPolicy for Generous {
+    fn pick(&self, board: &Board, pos: (usize, usize), colors: u8, rng: &mut StdRng) -> u8 {
+        let matching: Vec<u8> = (1..=colors).filter(|&color| board.would_match(pos, color)).collect();
+        if !matching.is_empty() && rng.gen_bool(self.chance.clamp(0.0, 1.0)) {
+            if let Some(&color) = matching.choose(rng) {
+                return color;
+            }
+        }
+        Uniform.pick(board, pos, colors, rng)
+    }
+}
+
+/// 内置的补充策略，可以写进 [`GameConfig`](crate::GameConfig) 中按关卡选择
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SpawnRule {
+    #[default]
+    Uniform,                              // 均匀随机
+    Weighted([u32; MAX_COLORS as usize]), // 按颜色权重
+    NoFreeMatches,                        // 不白送匹配
+    Generous(f64),                        // 按概率送匹配
+}
+
+impl SpawnRule {
+    /// 创建对应的补充策略
+    pub fn policy(&self) -> Arc<dyn SpawnPolicy> {
+        match *self {
+            SpawnRule::Uniform => Arc::new(Uniform),
+            SpawnRule::Weighted(weights) => Arc::new(Weighted {
+                weights: weights.to_vec(),
+            }),
+            SpawnRule::NoFreeMatches => Arc::new(NoFreeMatches),
+            SpawnRule::Generous(chance) => Arc::new(Generous { chance }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::board::tests::board;
+    use crate::game::{Game, GameConfig};
+    use crate::tile::Tile;
+
+    // 在同一个位置挑选多次，统计每种颜色被选中的次数（下标为颜色值）
+    fn tally(policy: &dyn SpawnPolicy, board: &Board, pos: (usize, usize), colors: u8) -> Vec<u32> {
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut counts = vec![0; colors as usize + 1];
+        for _ in 0..3000 {
+            counts[policy.pick(board, pos, colors, &mut rng) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn uniform_picks_every_color() {
+        let counts = tally(&Uniform, &board(&["..."]), (0, 0), 4);
+        assert_eq!(counts[0], 0);
+        assert!(counts[1..].iter().all(|&count| count > 600));
+    }
+
+    #[test]
+    fn weighted_follows_weights() {
+        let policy = Weighted { weights: vec![0, 3] };
+        let counts = tally(&policy, &board(&["..."]), (0, 0), 3);
+        assert_eq!(counts[1], 0);
+        assert!(counts[2] > 2 * counts[3]);
+
+        // 权重全为 0 时退回均匀随机
+        let counts = tally(&Weighted { weights: vec![0, 0, 0] }, &board(&["..."]), (0, 0), 3);
+        assert!(counts[1..].iter().all(|&count| count > 0));
+    }
+
+    #[test]
+    fn no_free_matches_avoids_matching_colors() {
+        let counts = tally(&NoFreeMatches, &board(&["11.22", "..3..", "....."]), (0, 2), 4);
+        assert_eq!((counts[1], counts[2]), (0, 0));
+        assert!(counts[3] > 0 && counts[4] > 0);
+
+        // 每种颜色都会连成三个时只能随便选
+        let counts = tally(&NoFreeMatches, &board(&["11.22", "..3..", "..3.."]), (0, 2), 3);
+        assert_eq!(counts.iter().sum::<u32>(), 3000);
+    }
+
+    #[test]
+    fn generous_gives_matching_colors() {
+        let board = board(&["11.22", "....."]);
+        let counts = tally(&Generous { chance: 1.0 }, &board, (0, 2), 4);
+        assert_eq!(counts[3] + counts[4], 0);
+        assert!(counts[1] > 0 && counts[2] > 0);
+
+        let counts = tally(&Generous { chance: 0.0 }, &board, (0, 2), 4);
+        assert!(counts[3] > 0 && counts[4] > 0);
+    }
+
+    // 总是补充同一种颜色的自定义策略
+    struct Always(u8);
+
+    impl SpawnPolicy for Always {
+        fn pick(&self, _board: &Board, _pos: (usize, usize), _colors: u8, _rng: &mut StdRng) -> u8 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn out_of_range_colors_are_clamped() {
+        for (color, expected) in [(0, 1), (9, 5)] {
+            let mut game = Game::with_config(GameConfig::default(), 4);
+            game.set_spawn_policy(Always(color));
+            let first = game.find_moves().into_iter().next().expect("没有可用移动");
+            assert!(game.try_swap(first.from, first.to));
+            game.remove_matches();
+            game.apply_gravity();
+            let filled = game.fill_empty();
+            assert!(filled.iter().all(|&pos| game.board()[pos] == Tile::gem(expected)));
+            assert!(!game.has_empty());
+        }
+    }
+
+    #[test]
+    fn game_refills_with_custom_policy() {
+        let mut game = Game::with_config(GameConfig::default(), 4);
+        game.set_spawn_policy(Always(2));
+        let first = game.find_moves().into_iter().next().expect("没有可用移动");
+        assert!(game.try_swap(first.from, first.to));
+        let cleared = game.remove_matches();
+        game.apply_gravity();
+        let filled = game.fill_empty();
+        assert_eq!(filled.len(), cleared.len());
+        assert!(filled.iter().all(|&pos| game.board()[pos] == Tile::gem(2)));
+    }
+}
@@ -0,0 +1,62 @@
+/// 特殊宝石类型
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Special {
+    #[default]
+    None,          // 普通宝石
+    StripedH,      // 横向条纹：被消除时清除整行
+    StripedV,      // 纵向条纹：被消除时清除整列
+    Wrapped,       // 包装宝石：被消除时炸开周围 3x3，自身留下并在下落后再炸一次
+    WrappedPrimed, // 已经炸过一次的包装宝石，下一轮消除时再次炸开 3x3
+    ColorBomb,     // 彩色炸弹：没有颜色，与任意宝石交换时清除棋盘上所有该颜色的宝石
+    Blocker,       // 障碍：没有颜色，不能交换也不会下落，相邻的宝石被消除或被特殊宝石波及时清除
+}
+
+/// 棋盘上的一个格子：宝石颜色加上特殊效果
+///
+/// 颜色为 0 表示空格（1=红，2=绿，3=蓝，4=黄，5=紫，6=橙，7=青，8=棕）。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub color: u8,        // 宝石颜色（0=空）
+    pub special: Special, // 特殊效果
+}
+
+impl Tile {
+    /// 空格
+    pub const EMPTY: Tile = Tile {
+        color: 0,
+        special: Special::None,
+    };
+
+    /// 彩色炸弹（颜色为 0，不参与普通匹配）
+    pub const COLOR_BOMB: Tile = Tile {
+        color: 0,
+        special: Special::ColorBomb,
+    };
+
+    /// 障碍（颜色为 0，不参与匹配）
+    pub const BLOCKER: Tile = Tile {
+        color: 0,
+        special: Special::Blocker,
+    };
+
+    /// 指定颜色的普通宝石
+    pub fn gem(color: u8) -> Self {
+        Tile {
+            color,
+            special: Special::None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Tile::EMPTY
+    }
+
+    /// 是否为特殊宝石（障碍不算）
+    pub fn is_special(&self) -> bool {
+        !matches!(self.special, Special::None | Special::Blocker)
+    }
+
+    pub fn is_blocker(&self) -> bool {
+        self.special == Special::Blocker
+    }
+}